@@ -0,0 +1,71 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput, Expr, ExprLit, Lit, Meta};
+
+/// Derives `component_key() -> &'static str` and `Display` for a struct documented with its
+/// component key, e.g. `/// alice.near/widget/Name`. Keys that would not parse as a
+/// `bos_loader::key::ComponentKey` are compile errors.
+#[proc_macro_derive(ComponentKey)]
+pub fn derive_component_key(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let key = input.attrs.iter().find_map(|attr| match &attr.meta {
+        Meta::NameValue(meta) if meta.path.is_ident("doc") => match &meta.value {
+            Expr::Lit(ExprLit {
+                lit: Lit::Str(doc), ..
+            }) => Some((doc.value().trim().to_string(), doc.span())),
+            _ => None,
+        },
+        _ => None,
+    });
+
+    let Some((key, span)) = key else {
+        return syn::Error::new_spanned(
+            &input.ident,
+            "ComponentKey requires a doc comment with the component key, e.g. /// alice.near/widget/Name",
+        )
+        .to_compile_error()
+        .into();
+    };
+
+    if let Err(err) = validate_key(&key) {
+        return syn::Error::new(span, format!("Invalid component key {:?}: {}", key, err))
+            .to_compile_error()
+            .into();
+    }
+
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #name #type_generics #where_clause {
+            pub const fn component_key() -> &'static str {
+                #key
+            }
+        }
+
+        impl #impl_generics ::std::fmt::Display for #name #type_generics #where_clause {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(Self::component_key())
+            }
+        }
+    }
+    .into()
+}
+
+/// Mirrors the checks of `ComponentKey::parse`, which this crate can't depend on
+fn validate_key(key: &str) -> Result<(), &'static str> {
+    if key.chars().any(char::is_whitespace) {
+        return Err("component keys can't contain whitespace");
+    }
+
+    if !key.contains('/') {
+        return Err("component keys must contain a '/' after the account");
+    }
+
+    if key.split('/').any(str::is_empty) {
+        return Err("component keys can't contain empty segments");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,348 @@
+use anyhow::anyhow;
+use std::{
+    convert::Infallible,
+    ffi::OsString,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
+use warp::{
+    http::{header, HeaderMap, Method, StatusCode},
+    hyper::body::HttpBody,
+    path::FullPath,
+    reject::{
+        InvalidHeader, InvalidQuery, LengthRequired, MethodNotAllowed, MissingHeader,
+        PayloadTooLarge, UnsupportedMediaType,
+    },
+    reply::Response,
+    Filter, Rejection, Reply,
+};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// A request as written to the access log
+#[derive(Clone, Debug)]
+pub struct AccessLogEntry {
+    pub remote_addr: Option<SocketAddr>,
+    pub time: SystemTime,
+    pub method: Method,
+    pub path: String,
+    pub status: StatusCode,
+    /// Size of the response body, unknown for streamed responses
+    pub bytes: Option<u64>,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl AccessLogEntry {
+    /// Formats the entry in Apache Combined Log Format, e.g.
+    /// `127.0.0.1 - - [10/Oct/2000:20:55:36 +0000] "GET / HTTP/1.1" 200 2326 "-" "curl/8.0"`
+    pub fn to_combined_log_format(&self) -> String {
+        format!(
+            "{} - - [{}] \"{} {} HTTP/1.1\" {} {} \"{}\" \"{}\"",
+            self.remote_addr
+                .map(|addr| addr.ip().to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            format_timestamp(self.time),
+            self.method,
+            escape(&self.path),
+            self.status.as_u16(),
+            // `-` rather than 0 for an empty body, as in Apache's %b
+            self.bytes
+                .filter(|bytes| *bytes > 0)
+                .map(|bytes| bytes.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            self.referer.as_deref().map(escape).unwrap_or("-".into()),
+            self.user_agent.as_deref().map(escape).unwrap_or("-".into()),
+        )
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The UTC year, month, day and seconds into the day of a time
+fn civil_time(time: SystemTime) -> (i64, i64, i64, u64) {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let (days, seconds_of_day) = ((seconds / 86400) as i64, seconds % 86400);
+
+    // civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day, seconds_of_day)
+}
+
+/// Formats a time as `10/Oct/2000:20:55:36 +0000`, always in UTC
+fn format_timestamp(time: SystemTime) -> String {
+    let (year, month, day, seconds_of_day) = civil_time(time);
+
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60
+    )
+}
+
+/// Formats a time in ISO 8601 as `2000-10-10T20:55:36Z`, always in UTC
+pub(crate) fn format_iso8601(time: SystemTime) -> String {
+    let (year, month, day, seconds_of_day) = civil_time(time);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60
+    )
+}
+
+/// Appends entries to an access log file, rotating it to `<path>.1` once it reaches `max_size` bytes
+#[derive(Clone, Debug)]
+pub struct AccessLog {
+    path: PathBuf,
+    max_size: Option<u64>,
+    lock: Arc<Mutex<()>>,
+}
+
+impl AccessLog {
+    pub fn new(path: PathBuf, max_size: Option<u64>) -> Self {
+        Self {
+            path,
+            max_size,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    fn rotated_path(&self) -> PathBuf {
+        let mut path = OsString::from(self.path.as_os_str());
+        path.push(".1");
+
+        path.into()
+    }
+
+    pub async fn append(&self, entry: &AccessLogEntry) -> Result<(), anyhow::Error> {
+        let _guard = self.lock.lock().await;
+
+        if let Some(max_size) = self.max_size {
+            let size = fs::metadata(&self.path)
+                .await
+                .map(|metadata| metadata.len())
+                .unwrap_or_default();
+
+            if size >= max_size {
+                let rotated_path = self.rotated_path();
+
+                fs::rename(&self.path, &rotated_path).await.map_err(|err| {
+                    anyhow!(
+                        "Failed to rotate access log {:?} to {:?} \n Error: {:?}",
+                        self.path,
+                        rotated_path,
+                        err
+                    )
+                })?;
+            }
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|err| anyhow!("Failed to open file {:?} \n Error: {:?}", self.path, err))?;
+
+        file.write_all(format!("{}\n", entry.to_combined_log_format()).as_bytes())
+            .await
+            .map_err(|err| anyhow!("Failed to write file {:?} \n Error: {:?}", self.path, err))?;
+
+        // tokio buffers file writes, flush so the entry is on disk before the next rotation check
+        file.flush()
+            .await
+            .map_err(|err| anyhow!("Failed to write file {:?} \n Error: {:?}", self.path, err))
+    }
+}
+
+/// The status warp responds with for the rejections the routes produce
+pub(crate) fn rejection_status(rejection: &Rejection) -> StatusCode {
+    if rejection.is_not_found() {
+        StatusCode::NOT_FOUND
+    } else if rejection.find::<MethodNotAllowed>().is_some() {
+        StatusCode::METHOD_NOT_ALLOWED
+    } else if rejection.find::<PayloadTooLarge>().is_some() {
+        StatusCode::PAYLOAD_TOO_LARGE
+    } else if rejection.find::<UnsupportedMediaType>().is_some() {
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    } else if rejection.find::<LengthRequired>().is_some() {
+        StatusCode::LENGTH_REQUIRED
+    } else if rejection
+        .find::<warp::filters::cors::CorsForbidden>()
+        .is_some()
+    {
+        StatusCode::FORBIDDEN
+    } else if rejection.find::<MissingHeader>().is_some()
+        || rejection.find::<InvalidHeader>().is_some()
+        || rejection.find::<InvalidQuery>().is_some()
+        || rejection
+            .find::<warp::filters::body::BodyDeserializeError>()
+            .is_some()
+    {
+        StatusCode::BAD_REQUEST
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// Logs every request handled by `filter` to `access_log`, leaving responses and rejections untouched
+pub(crate) fn with_access_log<F, R>(
+    filter: F,
+    access_log: Option<AccessLog>,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    warp::addr::remote()
+        .and(warp::method())
+        .and(warp::path::full())
+        .and(warp::header::headers_cloned())
+        .and(
+            filter
+                .map(|reply: R| Ok::<_, Rejection>(reply.into_response()))
+                .or_else(|rejection| async move { Ok::<_, Infallible>((Err(rejection),)) }),
+        )
+        .and_then(
+            move |remote_addr: Option<SocketAddr>,
+                  method: Method,
+                  path: FullPath,
+                  headers: HeaderMap,
+                  result: Result<Response, Rejection>| {
+                let access_log = access_log.clone();
+
+                async move {
+                    let Some(access_log) = access_log else {
+                        return result;
+                    };
+
+                    let (status, bytes) = match &result {
+                        Ok(response) => (response.status(), response.body().size_hint().exact()),
+                        Err(rejection) => (rejection_status(rejection), None),
+                    };
+                    let header = |name| {
+                        headers
+                            .get(name)
+                            .and_then(|value| value.to_str().ok())
+                            .map(str::to_string)
+                    };
+
+                    let entry = AccessLogEntry {
+                        remote_addr,
+                        time: SystemTime::now(),
+                        method,
+                        path: path.as_str().to_string(),
+                        status,
+                        bytes,
+                        referer: header(header::REFERER),
+                        user_agent: header(header::USER_AGENT),
+                    };
+
+                    if let Err(err) = access_log.append(&entry).await {
+                        eprintln!("Warning: could not write access log: {}", err);
+                    }
+
+                    result
+                }
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_combined_log_format() {
+        let entry = AccessLogEntry {
+            remote_addr: Some(([127, 0, 0, 1], 51234).into()),
+            time: UNIX_EPOCH + Duration::from_secs(971211336),
+            method: Method::GET,
+            path: "/".to_string(),
+            status: StatusCode::OK,
+            bytes: Some(2326),
+            referer: None,
+            user_agent: Some("curl \"8.0\"".to_string()),
+        };
+
+        assert_eq!(
+            entry.to_combined_log_format(),
+            "127.0.0.1 - - [10/Oct/2000:20:55:36 +0000] \"GET / HTTP/1.1\" 200 2326 \"-\" \"curl \\\"8.0\\\"\""
+        );
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_secs(951827696)),
+            "29/Feb/2000:12:34:56 +0000"
+        );
+    }
+
+    #[test]
+    fn test_format_iso8601() {
+        assert_eq!(
+            format_iso8601(UNIX_EPOCH + Duration::from_secs(971211336)),
+            "2000-10-10T20:55:36Z"
+        );
+        assert_eq!(format_iso8601(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+    }
+
+    #[tokio::test]
+    async fn test_access_log_rotation() {
+        let path = std::env::temp_dir().join("bos-loader-test-access.log");
+        let access_log = AccessLog::new(path.clone(), Some(1));
+        let _ = std::fs::remove_file(&path);
+        let entry = AccessLogEntry {
+            remote_addr: None,
+            time: UNIX_EPOCH,
+            method: Method::GET,
+            path: "/health".to_string(),
+            status: StatusCode::OK,
+            bytes: None,
+            referer: None,
+            user_agent: None,
+        };
+
+        access_log.append(&entry).await.unwrap();
+        access_log.append(&entry).await.unwrap();
+
+        let line =
+            "- - - [01/Jan/1970:00:00:00 +0000] \"GET /health HTTP/1.1\" 200 - \"-\" \"-\"\n";
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), line);
+        assert_eq!(
+            std::fs::read_to_string(access_log.rotated_path()).unwrap(),
+            line
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AccountPath {
+    pub path: PathBuf,
+    pub account: String,
+    /// Prepended to the `{prefix}` of every component key, e.g. `Vendor.` for `Vendor.Button`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+}
+
+/// Checks `account` against the NEAR account ID rules: 2 to 64 characters of lowercase letters,
+/// digits and the separators `.`, `-` and `_`, with no separator at either end or next to
+/// another one. Top-level (`near`) and implicit (64 hex characters) accounts are valid too, so
+/// no `.near` or `.testnet` suffix is required
+pub fn validate_account_id(account: &str) -> Result<(), anyhow::Error> {
+    if !(2..=64).contains(&account.len()) {
+        return Err(anyhow!(
+            "Invalid account ID {:?}, it must be 2 to 64 characters long",
+            account
+        ));
+    }
+
+    if account.chars().any(|char| char.is_ascii_uppercase()) {
+        return Err(anyhow!(
+            "Invalid account ID {:?}, account IDs are lowercase, did you mean {:?}?",
+            account,
+            account.to_ascii_lowercase()
+        ));
+    }
+
+    if let Some(char) = account
+        .chars()
+        .find(|char| !matches!(char, 'a'..='z' | '0'..='9' | '.' | '-' | '_'))
+    {
+        return Err(anyhow!(
+            "Invalid account ID {:?}, {:?} is not allowed, only a-z, 0-9, '.', '-' and '_' are",
+            account,
+            char
+        ));
+    }
+
+    let is_separator = |char: char| matches!(char, '.' | '-' | '_');
+
+    if account.starts_with(is_separator) || account.ends_with(is_separator) {
+        return Err(anyhow!(
+            "Invalid account ID {:?}, it can't start or end with '.', '-' or '_'",
+            account
+        ));
+    }
+
+    if account
+        .as_bytes()
+        .windows(2)
+        .any(|pair| is_separator(pair[0] as char) && is_separator(pair[1] as char))
+    {
+        return Err(anyhow!(
+            "Invalid account ID {:?}, '.', '-' and '_' can't follow each other",
+            account
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks a configured key prefix, which can't start with `id_separator`. Returns a warning
+/// when it doesn't end with the separator, as it is then joined to the names directly
+pub fn check_prefix(prefix: &str, id_separator: &str) -> Result<Option<String>, anyhow::Error> {
+    if prefix.starts_with(id_separator) {
+        return Err(anyhow!(
+            "Prefix {:?} can't start with {:?}",
+            prefix,
+            id_separator
+        ));
+    }
+
+    if prefix.ends_with(id_separator) {
+        Ok(None)
+    } else {
+        Ok(Some(format!(
+            "prefix {:?} doesn't end with {:?}, so names are appended directly, e.g. {}Button",
+            prefix, id_separator, prefix
+        )))
+    }
+}
+
+/// Accepted layouts of an accounts JSON file, a bare array or the `paths` key of a config file
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AccountsJson {
+    List(Vec<AccountPath>),
+    Config { paths: Vec<AccountPath> },
+}
+
+/// Reads account and path pairs from a JSON file, e.g. `[{"path": "./src", "account": "alice.near"}]`
+pub async fn read_accounts_json(path: &Path) -> Result<Vec<AccountPath>, anyhow::Error> {
+    let contents = fs::read_to_string(path)
+        .await
+        .map_err(|err| anyhow!("Failed to read path {:?} \n Error: {:?}", path, err))?;
+
+    let accounts = serde_json::from_str::<AccountsJson>(&contents).map_err(|_| {
+        anyhow!(
+            "Invalid accounts JSON format in {:?}, expected an array of {{\"path\", \"account\"}} objects",
+            path
+        )
+    })?;
+
+    Ok(match accounts {
+        AccountsJson::List(paths) | AccountsJson::Config { paths } => paths,
+    })
+}
+
+/// The set of account and path pairs components are served from
+#[derive(Clone, Debug, Default)]
+pub struct AccountRegistry {
+    paths: Vec<AccountPath>,
+}
+
+impl AccountRegistry {
+    pub fn from_paths(paths: Vec<AccountPath>) -> Self {
+        Self { paths }
+    }
+
+    /// Returns the first entry serving as `account`
+    pub fn find_by_account(&self, account: &str) -> Option<&AccountPath> {
+        self.paths.iter().find(|path| path.account == account)
+    }
+
+    pub fn add(&mut self, path: AccountPath) {
+        self.paths.push(path);
+    }
+
+    /// Removes every entry serving as `account`, returns whether any was removed
+    pub fn remove(&mut self, account: &str) -> bool {
+        let len = self.paths.len();
+        self.paths.retain(|path| path.account != account);
+
+        self.paths.len() != len
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, AccountPath> {
+        self.paths.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a AccountRegistry {
+    type Item = &'a AccountPath;
+    type IntoIter = std::slice::Iter<'a, AccountPath>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account_path(account: &str, path: &str) -> AccountPath {
+        AccountPath {
+            path: path.into(),
+            account: account.to_string(),
+            prefix: None,
+        }
+    }
+
+    #[test]
+    fn test_check_prefix() {
+        assert_eq!(check_prefix("Vendor.", ".").unwrap(), None);
+        assert!(check_prefix("Vendor", ".")
+            .unwrap()
+            .unwrap()
+            .contains("VendorButton"));
+        assert!(check_prefix(".Vendor.", ".").is_err());
+        assert_eq!(check_prefix("vendor/", "/").unwrap(), None);
+    }
+
+    #[test]
+    fn test_validate_account_id() {
+        for valid in [
+            "near",
+            "alice.near",
+            "bob.testnet",
+            "my_app-1.alice.near",
+            "98793cd91a3f870fb126f66285808c7e094afcfc4eda8a970f6648cdf0dbd6de",
+        ] {
+            assert!(validate_account_id(valid).is_ok(), "{valid}");
+        }
+
+        for invalid in [
+            "a",
+            "Alice.near",
+            "alice near",
+            "alice@near",
+            ".alice.near",
+            "alice.near.",
+            "-alice.near",
+            "alice..near",
+            "alice.-near",
+            &"a".repeat(65),
+        ] {
+            assert!(validate_account_id(invalid).is_err(), "{invalid}");
+        }
+
+        assert!(validate_account_id("Alice.near")
+            .unwrap_err()
+            .to_string()
+            .contains("\"alice.near\""));
+    }
+
+    #[test]
+    fn test_find_by_account() {
+        let registry = AccountRegistry::from_paths(vec![
+            account_path("near", "./components"),
+            account_path("alice.near", "./src"),
+        ]);
+
+        assert_eq!(
+            registry.find_by_account("alice.near"),
+            Some(&account_path("alice.near", "./src"))
+        );
+        assert_eq!(registry.find_by_account("bob.near"), None);
+    }
+
+    #[test]
+    fn test_add_and_remove() {
+        let mut registry = AccountRegistry::from_paths(vec![account_path("near", "./components")]);
+
+        registry.add(account_path("bob.near", "/tmp/bob"));
+        registry.add(account_path("bob.near", "/tmp/bob-vendor"));
+        assert_eq!(registry.len(), 3);
+
+        assert!(registry.remove("bob.near"));
+        assert!(!registry.remove("bob.near"));
+        assert_eq!(
+            registry.iter().collect::<Vec<_>>(),
+            vec![&account_path("near", "./components")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_accounts_json() {
+        assert_eq!(
+            read_accounts_json(Path::new("./test/accounts.json"))
+                .await
+                .unwrap(),
+            vec![
+                account_path("near", "./components"),
+                account_path("alice.near", "./src"),
+            ]
+        );
+        assert_eq!(
+            read_accounts_json(Path::new("./test/json-config/.bos-loader.json"))
+                .await
+                .unwrap(),
+            vec![account_path("near", "./components")]
+        );
+        assert!(read_accounts_json(Path::new("./test/replacements.json"))
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{components::ComponentCode, key::ComponentKey};
+use anyhow::anyhow;
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path};
+
+/// Bytes per KB in the budget file
+const KB: f64 = 1024.0;
+
+/// Limits on the size of the served component code, read from `--component-size-budget-file`,
+/// e.g. `{"total_kb": 500, "per_component_kb": 50, "components": {"alice.near/widget/Foo": 20}}`.
+/// The limit of a component in `components` replaces `per_component_kb` for it
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SizeBudget {
+    pub total_kb: Option<f64>,
+    pub per_component_kb: Option<f64>,
+    #[serde(default)]
+    pub components: HashMap<String, f64>,
+}
+
+fn format_kb(bytes: usize) -> String {
+    format!("{:.1} KB", bytes as f64 / KB)
+}
+
+impl SizeBudget {
+    pub fn from_file(path: &Path) -> Result<Self, anyhow::Error> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("Failed to read file {:?} \n Error: {:?}", path, err))?;
+
+        serde_json::from_str(&contents).map_err(|err| {
+            anyhow!(
+                "Failed to parse size budget file {:?} \n Error: {:?}",
+                path,
+                err
+            )
+        })
+    }
+
+    /// Describes every component, sorted by key, and the total that are over budget. Sizes are
+    /// the bytes of code after replacements, like `post_replacement_bytes` of the component stats
+    pub fn violations(&self, components: &HashMap<ComponentKey, ComponentCode>) -> Vec<String> {
+        let mut sizes = components
+            .iter()
+            .map(|(key, component)| (key, component.code.len()))
+            .collect::<Vec<(&ComponentKey, usize)>>();
+        sizes.sort();
+
+        let mut violations = sizes
+            .iter()
+            .filter_map(|(key, size)| {
+                let budget_kb = self
+                    .components
+                    .get(&key.to_string())
+                    .or(self.per_component_kb.as_ref())?;
+
+                (*size as f64 > budget_kb * KB).then(|| {
+                    format!(
+                        "{} is {}, over its budget of {} KB",
+                        key,
+                        format_kb(*size),
+                        budget_kb
+                    )
+                })
+            })
+            .collect::<Vec<String>>();
+
+        let total = sizes.iter().map(|(_, size)| size).sum::<usize>();
+        if let Some(total_kb) = self.total_kb {
+            if total as f64 > total_kb * KB {
+                violations.push(format!(
+                    "all components are {}, over the total budget of {} KB",
+                    format_kb(total),
+                    total_kb
+                ));
+            }
+        }
+
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn component(bytes: usize) -> ComponentCode {
+        ComponentCode {
+            code: "a".repeat(bytes),
+            css: None,
+            hash: None,
+            url: None,
+        }
+    }
+
+    #[test]
+    fn test_size_budget_violations() {
+        let budget: SizeBudget = serde_json::from_str(
+            r#"{"total_kb": 100, "per_component_kb": 50, "components": {"alice.near/widget/Foo": 20}}"#,
+        )
+        .unwrap();
+
+        let components = HashMap::from([
+            (
+                ComponentKey::parse("alice.near/widget/Foo").unwrap(),
+                component(30 * 1024),
+            ),
+            (
+                ComponentKey::parse("alice.near/widget/Bar").unwrap(),
+                component(40 * 1024),
+            ),
+            (
+                ComponentKey::parse("alice.near/widget/Baz").unwrap(),
+                component(60 * 1024),
+            ),
+        ]);
+
+        assert_eq!(
+            budget.violations(&components),
+            vec![
+                "alice.near/widget/Baz is 60.0 KB, over its budget of 50 KB",
+                "alice.near/widget/Foo is 30.0 KB, over its budget of 20 KB",
+                "all components are 130.0 KB, over the total budget of 100 KB",
+            ]
+        );
+
+        assert!(SizeBudget::default().violations(&components).is_empty());
+        assert!(serde_json::from_str::<SizeBudget>(r#"{"total": 100}"#).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{
+    accounts::AccountPath,
+    components::{ComponentCode, LoadReport},
+    key::ComponentKey,
+};
+use anyhow::anyhow;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+use tokio::{fs, sync::RwLock};
+
+/// Modification times of a directory and everything under it, sorted by path. Directories are
+/// included so removed files change them too
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreeTimestamps(Vec<(PathBuf, SystemTime)>);
+
+pub async fn tree_timestamps(root: &Path) -> Result<TreeTimestamps, anyhow::Error> {
+    let mut timestamps = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(path) = pending.pop() {
+        let metadata = fs::metadata(&path)
+            .await
+            .map_err(|err| anyhow!("Failed to read metadata of {:?} \n Error: {:?}", path, err))?;
+        let modified = metadata.modified().map_err(|err| {
+            anyhow!(
+                "Failed to read modification time of {:?} \n Error: {:?}",
+                path,
+                err
+            )
+        })?;
+
+        if metadata.is_dir() {
+            let mut entries = fs::read_dir(&path).await.map_err(|err| {
+                anyhow!("Failed to read directory {:?} \n Error: {:?}", path, err)
+            })?;
+
+            while let Some(entry) = entries
+                .next_entry()
+                .await
+                .map_err(|err| anyhow!("Failed to read directory {:?} \n Error: {:?}", path, err))?
+            {
+                pending.push(entry.path());
+            }
+        }
+
+        timestamps.push((path, modified));
+    }
+
+    timestamps.sort();
+
+    Ok(TreeTimestamps(timestamps))
+}
+
+/// The components of each account with the report of loading them, kept until the timestamps of
+/// its directory tree change
+#[derive(Clone, Debug, Default)]
+pub struct CacheState {
+    accounts: Arc<RwLock<HashMap<AccountPath, CachedAccount>>>,
+}
+
+#[derive(Clone, Debug)]
+struct CachedAccount {
+    timestamps: TreeTimestamps,
+    components: Arc<HashMap<ComponentKey, ComponentCode>>,
+    /// Replayed into the report of loads served from the cache
+    report: Arc<LoadReport>,
+}
+
+impl CacheState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The cached components of `account_path` and the report of loading them, if its tree still
+    /// has `timestamps`
+    pub async fn get(
+        &self,
+        account_path: &AccountPath,
+        timestamps: &TreeTimestamps,
+    ) -> Option<(Arc<HashMap<ComponentKey, ComponentCode>>, Arc<LoadReport>)> {
+        self.accounts
+            .read()
+            .await
+            .get(account_path)
+            .filter(|cached| cached.timestamps == *timestamps)
+            .map(|cached| (cached.components.clone(), cached.report.clone()))
+    }
+
+    /// Drops every cached account, so the next load reads all files again
+    pub async fn clear(&self) {
+        self.accounts.write().await.clear();
+    }
+
+    pub async fn insert(
+        &self,
+        account_path: AccountPath,
+        timestamps: TreeTimestamps,
+        components: Arc<HashMap<ComponentKey, ComponentCode>>,
+        report: Arc<LoadReport>,
+    ) {
+        self.accounts.write().await.insert(
+            account_path,
+            CachedAccount {
+                timestamps,
+                components,
+                report,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_tree_timestamps() {
+        let root = std::env::temp_dir().join("bos-loader-test-tree-timestamps");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("Sub")).unwrap();
+        std::fs::write(root.join("Sub/a.jsx"), "return <>A</>;").unwrap();
+
+        let timestamps = tree_timestamps(&root).await.unwrap();
+        assert_eq!(timestamps.0.len(), 3);
+        assert_eq!(tree_timestamps(&root).await.unwrap(), timestamps);
+
+        std::fs::File::options()
+            .write(true)
+            .open(root.join("Sub/a.jsx"))
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+
+        assert_ne!(tree_timestamps(&root).await.unwrap(), timestamps);
+    }
+
+    #[tokio::test]
+    async fn test_cache_state() {
+        let cache = CacheState::new();
+        let account_path = AccountPath {
+            path: "./test/components".into(),
+            account: "test.near".to_string(),
+            prefix: None,
+        };
+        let timestamps = tree_timestamps(&account_path.path).await.unwrap();
+
+        assert!(cache.get(&account_path, &timestamps).await.is_none());
+
+        cache
+            .insert(
+                account_path.clone(),
+                timestamps.clone(),
+                Arc::default(),
+                Arc::default(),
+            )
+            .await;
+
+        assert!(cache.get(&account_path, &timestamps).await.is_some());
+        assert!(cache
+            .get(&account_path, &TreeTimestamps(vec![]))
+            .await
+            .is_none());
+    }
+}
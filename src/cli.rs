@@ -0,0 +1,1906 @@
+use crate::{
+    accounts::{check_prefix, read_accounts_json, validate_account_id, AccountPath},
+    budget::SizeBudget,
+    components::{
+        key_pattern, HashAlgorithm, LintRule, COMPONENT_EXTENSIONS, DEFAULT_ID_SEPARATOR,
+        DEFAULT_KEY_FORMAT, DEFAULT_MAX_NESTING, TEST_MODE_ACCOUNT, WEB_ENGINE_KEY_FORMAT,
+    },
+    highlight::highlight_code,
+    replacements::{
+        decrypt_values, from_config_builder, invalid_url_values, parse_decryption_key,
+        parse_replacements, placeholder_keys, read_replacements_files,
+        replacement_files_from_config, replacements_from_env, replacements_from_env_prefix,
+        replacements_from_kv, write_replacements_schema, write_replacements_skeleton,
+        write_replacements_template, MergeStrategy, ReplacementsMap, DECRYPT_KEY_ENV,
+    },
+    rpc::{RpcClient, DEFAULT_RPC_URL},
+    server::{self, export_components, ndjson_line, run_server},
+    watch::WatchEventLogLevel,
+};
+use anyhow::{anyhow, bail};
+use clap::{Parser, Subcommand, ValueEnum};
+use config::{Config, ConfigError};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::Regex;
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    io::{IsTerminal, Write},
+    net::{IpAddr, Ipv4Addr},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::fs;
+use warp::http::HeaderName;
+
+/// How request logs are printed
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, for log aggregation
+    Json,
+}
+
+/// Installs the global `tracing` subscriber printing request logs to stdout in `format`
+pub fn init_logging(format: LogFormat) {
+    let subscriber = tracing_subscriber::fmt()
+        .with_target(false)
+        .with_ansi(std::io::stdout().is_terminal());
+
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().flatten_event(true).init(),
+    }
+}
+
+pub const TOML_CONFIG_FILE: &str = ".bos-loader.toml";
+pub const JSON_CONFIG_FILE: &str = ".bos-loader.json";
+
+/// Command line arguments of the `bos-loader` binary
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = "Serves the contents of BOS component files (.jsx) in a specified directory as a JSON object properly formatted for preview on a BOS gateway"
+)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Path to directory containing component files
+    #[clap(short, long, default_value = ".", value_hint = clap::ValueHint::DirPath)]
+    pub path: PathBuf,
+    /// Address to bind to, use 0.0.0.0 to bind all interfaces (e.g. in Docker or to share on a LAN)
+    #[arg(short = 'H', long, default_value = "127.0.0.1")]
+    pub host: IpAddr,
+    /// Port to serve on, 0 lets the OS pick a free port and prints it as PORT=<n>
+    #[arg(long, default_value = "3030")]
+    pub port: u16,
+    /// Serve on the first free port of this range instead of --port (e.g. 3030-3040), printed as PORT=<n>
+    #[arg(long, value_name = "START-END", conflicts_with = "port")]
+    pub port_range: Option<String>,
+    /// Base of the component URLs included in responses, e.g. when served behind a proxy [default: http://<HOST>:<PORT>]
+    #[arg(long, value_name = "URL")]
+    pub base_url: Option<String>,
+    /// NEAR account to use as component author in preview
+    pub account: Option<String>,
+    /// Use config file in current dir (./.bos-loader.toml or ./.bos-loader.json) to set account and path, causes other args to be ignored
+    #[arg(short = 'c')]
+    pub use_config: bool,
+    /// Read accounts and paths from a JSON file, e.g. [{"path": "./src", "account": "alice.near"}], causes the path and account args to be ignored
+    #[arg(long, value_name = "PATH", conflicts_with = "use_config")]
+    pub accounts_json: Option<PathBuf>,
+    /// Serve the paths of every .bos-loader.toml found under this directory, e.g. the packages of a monorepo, causes the path and account args to be ignored
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["use_config", "accounts_json"])]
+    pub workspace: Option<PathBuf>,
+    /// URL of a component list (e.g. another bos-loader) to serve alongside local components, local components win on key conflicts
+    #[arg(long, value_name = "URL")]
+    pub remote_source: Option<String>,
+    /// .tar.gz archive of components served as the first account, local components win on key conflicts
+    #[arg(long, value_name = "PATH")]
+    pub tar_source: Option<PathBuf>,
+    /// Serve a built-in set of example components instead of reading the filesystem, for testing the loader itself
+    #[arg(long, conflicts_with_all = ["use_config", "accounts_json", "workspace", "watch"])]
+    pub test_mode: bool,
+    /// Format of the request log printed to stdout
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+    /// Run in BOS Web Engine mode
+    #[arg(short = 'w', long, visible_alias = "bos-web-engine")]
+    pub web_engine: bool,
+    /// Load accounts concurrently, serving the accounts that loaded when others fail
+    #[arg(long)]
+    pub parallel_accounts: bool,
+    /// Include the accounts and paths components were loaded from as "metadata" in the response
+    #[arg(long)]
+    pub inject_account_metadata: bool,
+    /// Path to file with replacements map, repeat it to merge several in order with later files overriding earlier ones
+    #[clap(short, long, value_hint = clap::ValueHint::FilePath, action = clap::ArgAction::Append)]
+    pub replacements: Vec<PathBuf>,
+    /// Import every REPL_* environment variable as a replacement, overriding values from the replacements file
+    #[arg(long)]
+    pub replacements_from_env: bool,
+    /// Replacements as a JSON object (e.g. '{"REPL_FOO":"bar"}'), overriding values from the replacements file
+    #[arg(long, value_name = "JSON")]
+    pub replacements_inline: Option<String>,
+    /// A single replacement as KEY=VALUE (e.g. REPL_API_URL=https://staging.api.com), overriding the other replacement sources, repeat it for several
+    #[arg(long, value_name = "KEY=VALUE")]
+    pub replacements_kv: Vec<String>,
+    /// Import environment variables starting with this prefix as replacements keyed by the rest of their name (e.g. APP_ makes APP_API_URL ${API_URL}), the replacements file wins on conflicts
+    #[arg(short = 'e', long, value_name = "PREFIX")]
+    pub env_replacements: Option<String>,
+    /// Template for component keys, supports {account}, {prefix}, {name} and {ext} variables [default: {account}/widget/{prefix}{name}, or {account}/{prefix}{name} in BOS Web Engine mode]
+    #[arg(long, value_name = "FORMAT")]
+    pub component_key_format: Option<String>,
+    /// Separator joining directories and dot separated file name parts in nested component names (e.g. / for Nested/Child)
+    #[arg(long, value_name = "SEP", default_value = DEFAULT_ID_SEPARATOR)]
+    pub component_id_separator: String,
+    /// Skip files and directories matching this glob, relative to the component directory (e.g. '**/__tests__/**' or '*.stories.jsx'), repeat it for several
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+    /// Only serve components whose key matches this glob (e.g. 'alice.near/widget/Feed*'), repeat it for several
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+    /// Also replace placeholders, including ${REPL_ACCOUNT}, in component keys, e.g. of directories named after a placeholder
+    #[arg(long)]
+    pub replace_in_keys: bool,
+    /// Fail loading when a component file is larger than this many bytes, e.g. 1048576 for 1 MiB [default: unlimited]
+    #[arg(long, value_name = "BYTES")]
+    pub max_file_size: Option<u64>,
+    /// With --max-file-size, skip larger files with a warning instead of failing
+    #[arg(long, requires = "max_file_size")]
+    pub skip_oversized: bool,
+    /// Remove top-level `export default <name>` and `export { ... }` statements and `export`/`export default` keywords from components, keeping exported declarations and expressions
+    #[arg(long)]
+    pub strip_exports: bool,
+    /// Keep loaded components in memory and only re-read an account's files when modification times in its directory change
+    #[arg(long, conflicts_with = "component_age_check")]
+    pub cache: bool,
+    /// Leave ${REPL_ACCOUNT} placeholders untouched instead of replacing them with the account
+    #[arg(long)]
+    pub no_repl_account_injection: bool,
+    /// Include a hash of each component's code in the response
+    #[arg(long)]
+    pub component_hash: bool,
+    /// Algorithm used by --component-hash
+    #[arg(long, value_enum, default_value_t = HashAlgorithm::Blake3)]
+    pub component_hash_algorithm: HashAlgorithm,
+    /// Write a Markdown table documenting the replacement keys to this file
+    #[arg(long, value_name = "FILE", requires = "replacements")]
+    pub replacements_template: Option<PathBuf>,
+    /// Write a JSON Schema requiring every replacement key as a string to this file
+    #[arg(long, value_name = "PATH")]
+    pub replacements_schema_generate: Option<PathBuf>,
+    /// Write a replacements file with an empty value for every ${REPL_*} placeholder used by the components to this file
+    #[arg(long, value_name = "PATH")]
+    pub replacements_generate: Option<PathBuf>,
+    /// Reject replacement keys that are not uppercase letters, digits and underscores (e.g. REPL_API_URL)
+    #[arg(long)]
+    pub placeholder_strict_format: bool,
+    /// Substitute boolean and number replacement values as JavaScript literals (true) instead of strings ("true")
+    #[arg(long)]
+    pub replacements_coerce_types: bool,
+    /// Ignore lines starting with // in the replacements file, for JSON with comments
+    #[arg(long, requires = "replacements")]
+    pub replacements_comments_strip: bool,
+    /// Warn about replacement values that look like URLs but are malformed, e.g. `htps://` or `https:/`
+    #[arg(long)]
+    pub replacements_validate_urls: bool,
+    /// Lint rule to check component code against before serving, can be repeated
+    #[arg(long = "component-lint", value_enum, value_name = "RULE")]
+    pub component_lint: Vec<LintRule>,
+    /// Treat lint warnings as errors, excluding the offending components
+    #[arg(long)]
+    pub lint_strict: bool,
+    /// Warn about components whose source file was last modified more than SECONDS ago
+    #[arg(long, value_name = "SECONDS")]
+    pub component_age_check: Option<u64>,
+    /// Warn when component directories are nested more than N levels deep
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_NESTING)]
+    pub component_max_nesting: usize,
+    /// Secret required as `Authorization: Bearer <TOKEN>` by POST /reload and the account endpoints (POST /accounts, DELETE /accounts/:account). Without it POST /reload is open and the account endpoints are disabled
+    #[arg(long, value_name = "TOKEN")]
+    pub reload_token: Option<String>,
+    /// Name of the application root component, its key is returned as "entry_point" (e.g. Foo or alice.near/widget/Foo)
+    #[arg(long, value_name = "NAME")]
+    pub component_entry_point: Option<String>,
+    /// Record every substitution made while loading components to this JSONL file, overwritten on each load
+    #[arg(long, value_name = "PATH")]
+    pub replacements_audit_log: Option<PathBuf>,
+    /// Log replacement keys that were not used by any component after each load
+    #[arg(long)]
+    pub log_replacements_skipped: bool,
+    /// Write per-component size and timing statistics to this CSV file, overwritten on each load
+    #[arg(long, value_name = "PATH")]
+    pub component_stats_file: Option<PathBuf>,
+    /// Shut down after serving the first GET request, exiting with 1 if it failed
+    #[arg(long)]
+    pub exit_after_first_request: bool,
+    /// Write the response of the first GET request to this file
+    #[arg(long, value_name = "PATH", requires = "exit_after_first_request")]
+    pub output_file: Option<PathBuf>,
+    /// List the component files that would be loaded with their keys, without replacing placeholders, and exit
+    #[arg(long, conflicts_with_all = ["output", "exit_after_first_request", "watch", "test_mode"])]
+    pub dry_run: bool,
+    /// With --dry-run, also print the code of each component, syntax highlighted when stdout is a terminal
+    #[arg(long, requires = "dry_run")]
+    pub color_output: bool,
+    /// Serve the components as newline-delimited JSON, one {"key", "code"} object per line, instead of a single object. With --dry-run, print those lines instead of the file list
+    #[arg(long, conflicts_with_all = ["output", "color_output"])]
+    pub output_ndjson: bool,
+    /// Also list the distinct accounts of the served components as "accounts" in the GET / response
+    #[arg(long)]
+    pub serve_accounts_endpoint: bool,
+    /// Write the component JSON to this file and exit instead of starting a server
+    #[arg(short, long, value_name = "PATH", conflicts_with_all = ["exit_after_first_request", "watch", "reload_token"])]
+    pub output: Option<PathBuf>,
+    /// Load the components and run the startup checks without starting the server, e.g. in CI, exiting with 1 if any fails
+    #[arg(long, conflicts_with_all = ["output", "dry_run", "exit_after_first_request", "watch", "watch_trigger_file"])]
+    pub disable_serve: bool,
+    /// Comma separated request headers allowed by CORS preflight requests besides Authorization and Content-Type, which the admin endpoints need (e.g. X-Request-Id)
+    #[arg(long, value_name = "HEADERS", value_delimiter = ',')]
+    pub cors_allow_headers: Vec<HeaderName>,
+    /// Only allow cross-origin requests from this origin instead of any origin, repeat it for several (e.g. https://near.org)
+    #[arg(long, value_name = "ORIGIN")]
+    pub cors_origin: Vec<String>,
+    /// Also serve on the IPv6 loopback address [::1]
+    #[arg(long)]
+    pub serve_on_ipv6: bool,
+    /// Watch the component directories and stream `data: reload` Server-Sent Events on /events when .jsx/.tsx/.js/.ts files change
+    #[arg(long)]
+    pub watch: bool,
+    /// In watch mode, wait at least this many milliseconds after a reload before the next one, changes in between give one reload once it has passed
+    #[arg(long, value_name = "N", requires = "watch")]
+    pub watch_cooldown_ms: Option<u64>,
+    /// In watch mode, hold back a removed component for this many milliseconds and report it as modified if a component with the same key or file name is added meanwhile, e.g. on a rename or an atomic save spanning two reloads
+    #[arg(long, value_name = "MS", requires = "watch")]
+    pub watch_coalesce_timeout: Option<u64>,
+    /// In watch mode, append a JSON line per added, modified or removed component to this file, e.g. {"timestamp": "2000-10-10T20:55:36Z", "event_type": "modify", "key": "alice.near/widget/Foo", "path": "./src/Foo.jsx"}
+    #[arg(long, value_name = "PATH", requires = "watch")]
+    pub watch_emit_events: Option<PathBuf>,
+    /// Level added, modified and removed components are logged at in watch mode, debug hides them
+    #[arg(long, value_enum, value_name = "LEVEL", default_value_t = WatchEventLogLevel::Info, requires = "watch")]
+    pub watch_event_log_level: WatchEventLogLevel,
+    /// Reload all components whenever this file's modification time changes, e.g. with `touch /tmp/bos-reload`, for filesystems without reliable change events
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["output", "exit_after_first_request", "dry_run"])]
+    pub watch_trigger_file: Option<PathBuf>,
+    /// In watch mode, also watch the replacements files and reload every component with the new values when they change
+    #[arg(long, requires_all = ["replacements", "watch"])]
+    pub replacements_watch: bool,
+    /// Run this script after each successful reload in watch mode, with BOS_COMPONENT_COUNT and BOS_RELOAD_MS set
+    #[arg(long, value_name = "PATH", requires = "watch")]
+    pub reload_script: Option<PathBuf>,
+    /// Warn about components over the sizes of this JSON budget file, e.g. {"total_kb": 500, "per_component_kb": 50, "components": {"alice.near/widget/Foo": 20}}
+    #[arg(long, value_name = "PATH")]
+    pub component_size_budget_file: Option<PathBuf>,
+    /// Warn about component keys that don't fully match this regex (e.g. 'alice\.near/widget/[A-Z][A-Za-z]+')
+    #[arg(long, value_name = "PATTERN")]
+    pub component_key_regex_validate: Option<String>,
+    /// Fail instead of warning on checks that support it (--component-key-regex-validate, --component-size-budget-file, --replacements-validate-urls, --check-account-exists)
+    #[arg(long)]
+    pub strict: bool,
+    /// Check that every account exists on-chain before serving, to catch typos in account IDs
+    #[arg(long, conflicts_with = "test_mode")]
+    pub check_account_exists: bool,
+    /// NEAR JSON-RPC endpoint used by --check-account-exists
+    #[arg(long, value_name = "URL", default_value = DEFAULT_RPC_URL)]
+    pub rpc_url: String,
+    /// Use the active NEAR CLI account of the NEAR_ENV network (default testnet) from ~/.near-credentials as the account, and inject the network as ${REPL_NETWORK}
+    #[arg(long)]
+    pub replacements_from_near_config: bool,
+    /// Decrypt replacement values prefixed with "enc:" (base64 AES-256-GCM nonce and ciphertext)
+    #[arg(long)]
+    pub replacements_encrypt_values: bool,
+    /// 32 byte hex key used by --replacements-encrypt-values [default: REPL_DECRYPT_KEY env variable]
+    #[arg(long, value_name = "HEX", requires = "replacements_encrypt_values")]
+    pub replacements_key: Option<String>,
+    /// Print a histogram of component code sizes to stderr at startup
+    #[arg(long)]
+    pub component_size_histogram: bool,
+    /// Print the component hierarchy as an indented tree to stderr at startup
+    #[arg(long)]
+    pub component_tree: bool,
+    /// Append requests to this file in Apache Combined Log Format
+    #[arg(long, value_name = "PATH")]
+    pub access_log: Option<PathBuf>,
+    /// Rotate the access log to <PATH>.1 once it reaches this size in megabytes
+    #[arg(long, value_name = "MB", requires = "access_log")]
+    pub access_log_max_size_mb: Option<u64>,
+    /// Serve request, error and latency metrics for Prometheus on /metrics
+    #[arg(long)]
+    pub metrics: bool,
+}
+
+impl Args {
+    /// Names of the startup checks the arguments enable, listed by `--disable-serve`
+    pub fn enabled_checks(&self) -> Vec<&'static str> {
+        [
+            ("accounts exist", self.check_account_exists),
+            ("replacement URLs", self.replacements_validate_urls),
+            ("component lints", !self.component_lint.is_empty()),
+            ("component ages", self.component_age_check.is_some()),
+            (
+                "component key pattern",
+                self.component_key_regex_validate.is_some(),
+            ),
+            ("component sizes", self.component_size_budget_file.is_some()),
+            ("file sizes", self.max_file_size.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(check, enabled)| enabled.then_some(check))
+        .collect()
+    }
+
+    /// `--component-key-format`, or the default format of the mode
+    pub fn key_format(&self) -> String {
+        self.component_key_format.clone().unwrap_or_else(|| {
+            if self.web_engine {
+                WEB_ENGINE_KEY_FORMAT.to_string()
+            } else {
+                DEFAULT_KEY_FORMAT.to_string()
+            }
+        })
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print how the component key of a file is derived, honouring -w, --component-key-format and --component-id-separator
+    ExplainKey {
+        /// Path to the component file
+        #[arg(long, value_name = "FILE")]
+        path: PathBuf,
+        /// Directory containing component files the key is derived relative to
+        #[arg(long, value_name = "DIR", default_value = ".")]
+        root: PathBuf,
+        /// NEAR account to use as component author
+        account: String,
+    },
+}
+
+/// What the loader does with the resolved settings, see [`run`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum RunMode {
+    /// Serve the components until interrupted
+    #[default]
+    Serve,
+    /// `--disable-serve`: load the components once, run the startup checks and exit
+    Check,
+    /// `--dry-run`: list the component files that would be loaded and exit
+    DryRun,
+    /// `--output`: write the component JSON to this file and exit
+    Export(PathBuf),
+}
+
+/// `--replacements-template`: the replacements files it documents, with the map read from them
+/// alone before the other replacement sources are merged in
+#[derive(Clone, Debug)]
+pub struct ReplacementsTemplate {
+    pub path: PathBuf,
+    pub replacements_files: Vec<PathBuf>,
+    pub replacements_map: ReplacementsMap,
+}
+
+/// The replacement sources, kept so `--replacements-watch` can re-read the replacements files and
+/// merge the other sources into them like at startup
+#[derive(Clone, Debug, Default)]
+pub struct ReplacementsSources {
+    pub replacements_files: Vec<PathBuf>,
+    pub strict_format: bool,
+    pub coerce_types: bool,
+    pub comments_strip: bool,
+    /// Config file and `--env-replacements` values, the replacements files win on conflicts
+    pub defaults: ReplacementsMap,
+    /// `REPL_NETWORK`, `--replacements-from-env`, `--replacements-inline` and `--replacements-kv`
+    /// values, overriding the replacements files
+    pub overrides: ReplacementsMap,
+    /// Decrypts the "enc:" values with `--replacements-encrypt-values`
+    pub decryption_key: Option<[u8; 32]>,
+}
+
+impl ReplacementsSources {
+    /// Reads the replacements files alone, later files overriding earlier ones
+    pub async fn read_files(&self) -> Result<ReplacementsMap, anyhow::Error> {
+        read_replacements_files(
+            &self.replacements_files,
+            self.strict_format,
+            self.coerce_types,
+            self.comments_strip,
+        )
+        .await
+        .map_err(|err| {
+            anyhow!(
+                "Something went wrong while parsing the replacement file: {}",
+                err
+            )
+        })
+    }
+
+    /// Merges the other sources into the map read from the replacements files, then decrypts
+    /// and resolves the references between values
+    pub fn merge(
+        &self,
+        mut replacements_map: ReplacementsMap,
+    ) -> Result<ReplacementsMap, anyhow::Error> {
+        replacements_map.merge(self.defaults.clone(), MergeStrategy::KeepExisting);
+        replacements_map.merge(self.overrides.clone(), MergeStrategy::Override);
+
+        if let Some(key) = &self.decryption_key {
+            decrypt_values(&mut replacements_map, key).map_err(|err| {
+                anyhow!(
+                    "Something went wrong while decrypting the replacements: {}",
+                    err
+                )
+            })?;
+        }
+
+        replacements_map.resolve_references().map_err(|err| {
+            anyhow!(
+                "Something went wrong while resolving the replacements: {}",
+                err
+            )
+        })
+    }
+
+    /// Re-reads the replacements files and merges the other sources into them
+    pub async fn load(&self) -> Result<ReplacementsMap, anyhow::Error> {
+        self.merge(self.read_files().await?)
+    }
+}
+
+/// Settings the server runs with, after merging CLI arguments and the config file
+#[derive(Clone, Debug)]
+pub struct ResolvedConfig {
+    pub mode: RunMode,
+    pub log_format: LogFormat,
+    /// With [`RunMode::DryRun`], also print the code of each component
+    pub color_output: bool,
+    /// Startup checks enabled by the arguments, listed in [`RunMode::Check`]
+    pub checks: Vec<&'static str>,
+    pub replacements_template: Option<ReplacementsTemplate>,
+    /// Where to write the JSON Schema of the replacement keys at startup
+    pub replacements_schema_file: Option<PathBuf>,
+    /// Where to write a replacements file with every placeholder the components use at startup
+    pub replacements_generate: Option<PathBuf>,
+    pub accounts: Vec<AccountPath>,
+    /// URL of a component list merged below the local components
+    pub remote_source: Option<String>,
+    /// `.tar.gz` archive of components served as the first account, below its local components
+    pub tar_source: Option<PathBuf>,
+    /// Serve the built-in [`crate::components::TEST_MODE_COMPONENTS`] instead of the account paths
+    pub test_mode: bool,
+    /// Address to bind to, `0.0.0.0` for all interfaces
+    pub host: IpAddr,
+    pub port: u16,
+    /// Ports tried in order instead of `port`, the first free one is used
+    pub port_range: Option<RangeInclusive<u16>>,
+    /// Base of the component URLs in responses [default: http://<host>:<port>]
+    pub base_url: Option<String>,
+    pub web_engine: bool,
+    pub parallel_accounts: bool,
+    pub inject_account_metadata: bool,
+    pub key_format: String,
+    pub replacements_map: Arc<ReplacementsMap>,
+    pub inject_repl_account: bool,
+    pub hash_algorithm: Option<HashAlgorithm>,
+    pub lint_rules: Vec<LintRule>,
+    pub lint_strict: bool,
+    pub max_component_age: Option<Duration>,
+    pub max_nesting: usize,
+    pub id_separator: String,
+    pub strip_exports: bool,
+    /// Files and directories skipped while loading, relative to the account directory
+    pub exclude: Arc<GlobSet>,
+    /// Only components with a matching key are loaded, all when empty
+    pub include: Arc<GlobSet>,
+    /// Replace placeholders in component keys too
+    pub replace_in_keys: bool,
+    /// Size in bytes above which component files aren't read
+    pub max_file_size: Option<u64>,
+    /// Skip oversized files with a warning instead of failing the load
+    pub skip_oversized: bool,
+    /// Reuse the components of accounts whose directory tree is unchanged since the last load
+    pub cache: bool,
+    pub reload_token: Option<String>,
+    pub entry_point: Option<String>,
+    pub replacements_audit_log: Option<PathBuf>,
+    pub log_replacements_skipped: bool,
+    pub component_stats_file: Option<PathBuf>,
+    /// Checked after every load, warning (or failing with `strict`) about components over budget
+    pub size_budget: Option<SizeBudget>,
+    pub cors_allow_headers: Vec<HeaderName>,
+    /// Origins allowed by CORS, any origin when empty
+    pub cors_origins: Vec<String>,
+    pub serve_on_ipv6: bool,
+    /// Reload when component files change, notifying `/events` subscribers
+    pub watch: bool,
+    /// Time after a watch mode reload during which changes wait for the next one
+    pub watch_cooldown: Option<Duration>,
+    /// Time a watch mode removal waits for an addition of the same component
+    pub watch_coalesce_timeout: Option<Duration>,
+    /// Appended a JSON line per watch event
+    pub watch_emit_events: Option<PathBuf>,
+    pub watch_event_log_level: WatchEventLogLevel,
+    /// Reload, like watch mode but without filesystem events, when this file's mtime changes
+    pub watch_trigger_file: Option<PathBuf>,
+    /// Re-read the replacements files and reload when they change, in watch mode
+    pub replacements_watch: bool,
+    pub replacements_sources: ReplacementsSources,
+    /// Run after each successful watch mode reload
+    pub reload_script: Option<PathBuf>,
+    /// Anchored pattern every component key must match
+    pub key_pattern: Option<Regex>,
+    /// Turn warnings into errors where supported
+    pub strict: bool,
+    pub exit_after_first_request: bool,
+    pub output_file: Option<PathBuf>,
+    /// Serve the components as one `{"key", "code"}` JSON line each instead of a `FileList`
+    pub output_ndjson: bool,
+    /// Print a histogram of component sizes to stderr at startup
+    pub component_size_histogram: bool,
+    /// Print the component keys as a tree to stderr at startup
+    pub component_tree: bool,
+    /// Include the sorted accounts of the component keys in the `FileList`
+    pub serve_accounts_endpoint: bool,
+    pub access_log: Option<PathBuf>,
+    /// Size in bytes after which the access log is rotated
+    pub access_log_max_size: Option<u64>,
+    /// Serve request counts and latencies on `/metrics`
+    pub metrics: bool,
+}
+
+impl Default for ResolvedConfig {
+    fn default() -> Self {
+        Self {
+            mode: RunMode::default(),
+            log_format: LogFormat::default(),
+            color_output: false,
+            checks: vec![],
+            replacements_template: None,
+            replacements_schema_file: None,
+            replacements_generate: None,
+            accounts: vec![],
+            remote_source: None,
+            tar_source: None,
+            test_mode: false,
+            host: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 3030,
+            port_range: None,
+            base_url: None,
+            web_engine: false,
+            parallel_accounts: false,
+            inject_account_metadata: false,
+            key_format: DEFAULT_KEY_FORMAT.to_string(),
+            replacements_map: Arc::new(ReplacementsMap::default()),
+            inject_repl_account: true,
+            hash_algorithm: None,
+            lint_rules: vec![],
+            lint_strict: false,
+            max_component_age: None,
+            max_nesting: DEFAULT_MAX_NESTING,
+            id_separator: DEFAULT_ID_SEPARATOR.to_string(),
+            strip_exports: false,
+            exclude: Arc::new(GlobSet::empty()),
+            include: Arc::new(GlobSet::empty()),
+            replace_in_keys: false,
+            max_file_size: None,
+            skip_oversized: false,
+            cache: false,
+            reload_token: None,
+            entry_point: None,
+            replacements_audit_log: None,
+            log_replacements_skipped: false,
+            component_stats_file: None,
+            size_budget: None,
+            cors_allow_headers: vec![],
+            cors_origins: vec![],
+            serve_on_ipv6: false,
+            watch: false,
+            watch_cooldown: None,
+            watch_coalesce_timeout: None,
+            watch_emit_events: None,
+            watch_event_log_level: WatchEventLogLevel::default(),
+            watch_trigger_file: None,
+            replacements_watch: false,
+            replacements_sources: ReplacementsSources::default(),
+            reload_script: None,
+            key_pattern: None,
+            strict: false,
+            exit_after_first_request: false,
+            output_file: None,
+            output_ndjson: false,
+            component_size_histogram: false,
+            component_tree: false,
+            serve_accounts_endpoint: false,
+            access_log: None,
+            access_log_max_size: None,
+            metrics: false,
+        }
+    }
+}
+
+/// The network and active account of the NEAR CLI
+#[derive(Clone, Debug, PartialEq)]
+pub struct NearCliConfig {
+    pub network: String,
+    pub account: String,
+}
+
+#[derive(Deserialize)]
+struct NearCredentials {
+    account_id: String,
+}
+
+/// Reads the active account of `near_env` (defaults to testnet) from `<home>/.near-credentials/<network>/`,
+/// the most recently modified credentials file being the active one
+pub async fn read_near_cli_config(
+    home: &Path,
+    near_env: Option<String>,
+) -> Result<Option<NearCliConfig>, anyhow::Error> {
+    let network = near_env.unwrap_or_else(|| "testnet".to_string());
+    let credentials_dir = home.join(".near-credentials").join(&network);
+
+    if !credentials_dir.is_dir() {
+        return Ok(None);
+    }
+
+    let mut entries = fs::read_dir(&credentials_dir).await.map_err(|err| {
+        anyhow!(
+            "Could not read directory {:?} \n Error: {:?}",
+            credentials_dir,
+            err
+        )
+    })?;
+
+    let mut newest: Option<(SystemTime, PathBuf)> = None;
+
+    while let Some(entry) = entries.next_entry().await.map_err(|err| {
+        anyhow!(
+            "Could not read directory entries for path {:?} \n Error: {:?}",
+            credentials_dir,
+            err
+        )
+    })? {
+        let path = entry.path();
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+
+        let modified = entry
+            .metadata()
+            .await
+            .and_then(|metadata| metadata.modified())
+            .map_err(|err| {
+                anyhow!(
+                    "Failed to read modification time of file {:?} \n Error: {:?}",
+                    path,
+                    err
+                )
+            })?;
+
+        if newest
+            .as_ref()
+            .is_none_or(|(newest_modified, _)| modified > *newest_modified)
+        {
+            newest = Some((modified, path));
+        }
+    }
+
+    let Some((_, credentials_path)) = newest else {
+        return Ok(None);
+    };
+
+    let contents = fs::read_to_string(&credentials_path).await.map_err(|err| {
+        anyhow!(
+            "Failed to read path {:?} \n Error: {:?}",
+            credentials_path,
+            err
+        )
+    })?;
+
+    let NearCredentials { account_id } = serde_json::from_str(&contents).map_err(|_| {
+        anyhow!(
+            "Invalid JSON format in credentials file {:?}",
+            credentials_path
+        )
+    })?;
+
+    Ok(Some(NearCliConfig {
+        network,
+        account: account_id,
+    }))
+}
+
+/// Finds every `.bos-loader.toml` under `root` and merges their paths, each resolved relative
+/// to the directory of its config file. Hidden directories and `node_modules` are skipped, and
+/// directories reached again through symlinks are only visited once
+pub async fn discover_workspace(root: &Path) -> Result<Vec<AccountPath>, anyhow::Error> {
+    let mut visited = HashSet::new();
+    let mut pending = vec![root.to_path_buf()];
+    let mut config_files = vec![];
+
+    while let Some(dir) = pending.pop() {
+        let canonical_dir = fs::canonicalize(&dir)
+            .await
+            .map_err(|err| anyhow!("Could not resolve path {:?} \n Error: {:?}", dir, err))?;
+
+        if !visited.insert(canonical_dir) {
+            continue;
+        }
+
+        let mut entries = fs::read_dir(&dir)
+            .await
+            .map_err(|err| anyhow!("Could not read directory {:?} \n Error: {:?}", dir, err))?;
+
+        while let Some(entry) = entries.next_entry().await.map_err(|err| {
+            anyhow!(
+                "Could not read directory entries for path {:?} \n Error: {:?}",
+                dir,
+                err
+            )
+        })? {
+            let path = entry.path();
+            let file_name = entry.file_name().to_string_lossy().to_string();
+
+            if file_name == TOML_CONFIG_FILE {
+                config_files.push(path);
+                continue;
+            }
+
+            if file_name.starts_with('.') || file_name == "node_modules" {
+                continue;
+            }
+
+            // follows symlinks, cycles are caught by the visited set
+            if fs::metadata(&path)
+                .await
+                .is_ok_and(|metadata| metadata.is_dir())
+            {
+                pending.push(path);
+            }
+        }
+    }
+
+    config_files.sort();
+
+    let mut account_paths = vec![];
+
+    for config_file in config_files {
+        let settings = Config::builder()
+            .add_source(config::File::from(config_file.clone()))
+            .build()
+            .map_err(|err| {
+                anyhow!(
+                    "Failed to load config file {:?} \n Error: {:?}",
+                    config_file,
+                    err
+                )
+            })?;
+        let paths = settings.get::<Vec<AccountPath>>("paths").map_err(|err| {
+            anyhow!(
+                "A valid path configuration was not found in config file {:?} \n Error: {:?}",
+                config_file,
+                err
+            )
+        })?;
+        let config_dir = config_file.parent().unwrap_or(root);
+
+        account_paths.extend(paths.into_iter().map(|account_path| AccountPath {
+            path: config_dir.join(&account_path.path),
+            ..account_path
+        }));
+    }
+
+    Ok(account_paths)
+}
+
+/// Parses a `--port-range` like `3030-3040`, both ends included
+pub fn parse_port_range(value: &str) -> Result<RangeInclusive<u16>, anyhow::Error> {
+    let (start, end) = value
+        .split_once('-')
+        .and_then(|(start, end)| {
+            Some((
+                start.trim().parse::<u16>().ok()?,
+                end.trim().parse::<u16>().ok()?,
+            ))
+        })
+        .ok_or(anyhow!(
+            "Invalid port range {:?}, expected <START>-<END>",
+            value
+        ))?;
+
+    if start == 0 || start > end {
+        return Err(anyhow!(
+            "Invalid port range {:?}, expected 1 <= START <= END",
+            value
+        ));
+    }
+
+    Ok(start..=end)
+}
+
+/// Parses an HTTP origin like `https://near.org`, normalized the way browsers send it in the
+/// `Origin` header (lowercase host, no default port or trailing slash)
+pub fn parse_cors_origin(value: &str) -> Result<String, anyhow::Error> {
+    let url = url::Url::parse(value)
+        .map_err(|err| anyhow!("Invalid origin {:?} \n Error: {:?}", value, err))?;
+
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none_or(str::is_empty) {
+        return Err(anyhow!(
+            "Invalid origin {:?}, expected http(s)://<host>[:<port>]",
+            value
+        ));
+    }
+
+    if url.path() != "/"
+        || url.query().is_some()
+        || url.fragment().is_some()
+        || !url.username().is_empty()
+        || url.password().is_some()
+    {
+        return Err(anyhow!(
+            "Invalid origin {:?}, an origin can't have a path, query or credentials",
+            value
+        ));
+    }
+
+    Ok(url.origin().ascii_serialization())
+}
+
+/// Reads an optional array of strings like `cors_origins` from a config file, empty when missing
+pub fn string_list_from_config(config: &Config, key: &str) -> Result<Vec<String>, anyhow::Error> {
+    match config.get::<Vec<String>>(key) {
+        Ok(values) => Ok(values),
+        Err(ConfigError::NotFound(_)) => Ok(vec![]),
+        Err(err) => Err(anyhow!(
+            "Invalid {} in config file \n Error: {:?}",
+            key,
+            err
+        )),
+    }
+}
+
+/// Compiles `--exclude` and `--include` patterns such as `**/__tests__/**` or
+/// `alice.near/widget/Feed*`, where `*` also matches across `/`
+pub fn glob_set(patterns: &[String]) -> Result<GlobSet, anyhow::Error> {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        builder.add(
+            Glob::new(pattern)
+                .map_err(|err| anyhow!("Invalid glob pattern {:?} \n Error: {}", pattern, err))?,
+        );
+    }
+
+    builder
+        .build()
+        .map_err(|err| anyhow!("Failed to compile glob patterns \n Error: {}", err))
+}
+
+/// Picks the config file to use from `dir`, preferring TOML over JSON when both exist
+pub fn find_config_file(dir: &Path) -> PathBuf {
+    let toml_path = dir.join(TOML_CONFIG_FILE);
+    let json_path = dir.join(JSON_CONFIG_FILE);
+
+    match (toml_path.exists(), json_path.exists()) {
+        (true, true) => {
+            eprintln!(
+                "Warning: found both {} and {}, using {}",
+                TOML_CONFIG_FILE, JSON_CONFIG_FILE, TOML_CONFIG_FILE
+            );
+            toml_path
+        }
+        (false, true) => json_path,
+        _ => toml_path,
+    }
+}
+
+/// A config file picked with `-c`
+pub enum ConfigFile {
+    /// A `.bos-loader.json` holding just the array of paths, read like `--accounts-json`
+    Paths(Vec<AccountPath>),
+    /// Any other config file, JSON or TOML, with the schema of `.bos-loader.toml`
+    Settings(Config),
+}
+
+/// Reads the config file at `path`, see [`ConfigFile`]
+pub async fn load_config_file(path: &Path) -> Result<ConfigFile, anyhow::Error> {
+    if path.ends_with(JSON_CONFIG_FILE) {
+        let contents = fs::read_to_string(path)
+            .await
+            .map_err(|err| anyhow!("Failed to read path {:?} \n Error: {:?}", path, err))?;
+
+        if contents.trim_start().starts_with('[') {
+            return read_accounts_json(path)
+                .await
+                .map(ConfigFile::Paths)
+                .map_err(|err| {
+                    anyhow!("Something went wrong while reading the accounts: {}", err)
+                });
+        }
+    }
+
+    Config::builder()
+        .add_source(config::File::from(path.to_path_buf()))
+        .build()
+        .map(ConfigFile::Settings)
+        .map_err(|err| anyhow!("Failed to load config file: {}", err))
+}
+
+/// Resolves the settings the server runs with from the command line arguments: reads the config
+/// file, accounts and replacements they point to, merges them with the arguments and validates
+/// the result. Files requested by `--replacements-template`, `--replacements-schema-generate` and
+/// `--replacements-generate` aren't written, which [`run`] does, and `command` is ignored
+pub async fn resolve_config(args: Args) -> Result<ResolvedConfig, anyhow::Error> {
+    let key_format = args.key_format();
+    let checks = args.enabled_checks();
+    let mode = if args.disable_serve {
+        RunMode::Check
+    } else if args.dry_run {
+        RunMode::DryRun
+    } else if let Some(output) = &args.output {
+        RunMode::Export(output.clone())
+    } else {
+        RunMode::Serve
+    };
+    let Args {
+        path,
+        host,
+        port,
+        port_range,
+        base_url,
+        account,
+        use_config,
+        accounts_json,
+        workspace,
+        remote_source,
+        tar_source,
+        test_mode,
+        web_engine,
+        parallel_accounts,
+        inject_account_metadata,
+        replacements,
+        replacements_from_env: import_env_replacements,
+        replacements_inline,
+        replacements_kv,
+        env_replacements,
+        component_id_separator,
+        strip_exports,
+        exclude,
+        include,
+        replace_in_keys,
+        max_file_size,
+        skip_oversized,
+        cache,
+        no_repl_account_injection,
+        component_hash,
+        component_hash_algorithm,
+        placeholder_strict_format,
+        replacements_coerce_types,
+        replacements_comments_strip,
+        replacements_validate_urls,
+        component_lint,
+        lint_strict,
+        component_age_check,
+        component_max_nesting,
+        reload_token,
+        component_entry_point,
+        replacements_audit_log,
+        log_replacements_skipped,
+        component_stats_file,
+        exit_after_first_request,
+        output_file,
+        output_ndjson,
+        serve_accounts_endpoint,
+        cors_allow_headers,
+        cors_origin,
+        serve_on_ipv6,
+        watch,
+        watch_cooldown_ms,
+        watch_coalesce_timeout,
+        watch_emit_events,
+        watch_event_log_level,
+        watch_trigger_file,
+        replacements_watch,
+        reload_script,
+        component_size_budget_file,
+        component_key_regex_validate,
+        strict,
+        check_account_exists,
+        rpc_url,
+        replacements_from_near_config,
+        replacements_encrypt_values,
+        replacements_key,
+        component_size_histogram,
+        component_tree,
+        access_log,
+        access_log_max_size_mb,
+        metrics,
+        log_format,
+        color_output,
+        replacements_template,
+        replacements_schema_generate,
+        replacements_generate,
+        ..
+    } = args;
+
+    let near_cli_config = if replacements_from_near_config {
+        let home = std::env::var_os("HOME")
+            .or_else(|| std::env::var_os("USERPROFILE"))
+            .map(PathBuf::from)
+            .unwrap_or_default();
+
+        let near_cli_config = read_near_cli_config(&home, std::env::var("NEAR_ENV").ok())
+            .await
+            .map_err(|err| {
+                anyhow!(
+                    "Something went wrong while reading the NEAR CLI config: {}",
+                    err
+                )
+            })?;
+
+        if near_cli_config.is_none() {
+            eprintln!("Warning: no NEAR CLI credentials found, using the account argument");
+        }
+
+        near_cli_config
+    } else {
+        None
+    };
+
+    let (config_paths, settings) = if use_config {
+        match load_config_file(&find_config_file(Path::new("."))).await? {
+            ConfigFile::Paths(paths) => (Some(paths), None),
+            ConfigFile::Settings(settings) => (None, Some(settings)),
+        }
+    } else {
+        (None, None)
+    };
+
+    let account_paths = if let Some(workspace) = workspace {
+        let account_paths = discover_workspace(&workspace)
+            .await
+            .map_err(|err| anyhow!("Something went wrong while reading the workspace: {}", err))?;
+
+        if account_paths.is_empty() {
+            bail!(
+                "No {} files with paths were found in {:?}",
+                TOML_CONFIG_FILE,
+                workspace
+            );
+        }
+
+        account_paths
+    } else if let Some(accounts_json) = accounts_json {
+        read_accounts_json(&accounts_json)
+            .await
+            .map_err(|err| anyhow!("Something went wrong while reading the accounts: {}", err))?
+    } else if let Some(config_paths) = config_paths {
+        config_paths
+    } else if let Some(settings) = &settings {
+        settings.get::<Vec<AccountPath>>("paths").map_err(|err| {
+            anyhow!(
+                "A valid path configuration was not found in config file: {}",
+                err
+            )
+        })?
+    } else {
+        vec![AccountPath {
+            path,
+            account: near_cli_config
+                .as_ref()
+                .map(|near_cli_config| near_cli_config.account.clone())
+                .or(account)
+                .or_else(|| test_mode.then(|| TEST_MODE_ACCOUNT.to_string()))
+                .ok_or(anyhow!(
+                    "Account ID must be provided when not using configuration file"
+                ))?,
+            prefix: None,
+        }]
+    };
+
+    for account_path in &account_paths {
+        validate_account_id(&account_path.account)?;
+
+        if let Some(prefix) = &account_path.prefix {
+            if let Some(warning) = check_prefix(prefix, &component_id_separator).map_err(|err| {
+                anyhow!(
+                    "Invalid prefix for account {}: {}",
+                    account_path.account,
+                    err
+                )
+            })? {
+                eprintln!("Warning: {}", warning);
+            }
+        }
+    }
+
+    if check_account_exists {
+        let client = RpcClient::new(&rpc_url);
+        let mut accounts = account_paths
+            .iter()
+            .map(|account_path| account_path.account.as_str())
+            .collect::<Vec<&str>>();
+        accounts.sort();
+        accounts.dedup();
+
+        for account in accounts {
+            let message = match client.account_exists(account).await {
+                Ok(true) => continue,
+                Ok(false) => format!("Account {} does not exist on {}", account, rpc_url),
+                Err(err) => format!(
+                    "Could not check whether account {} exists: {}",
+                    account, err
+                ),
+            };
+
+            if strict {
+                bail!("{}", message);
+            }
+
+            eprintln!("Warning: {}", message);
+        }
+    }
+
+    let config_cors_origins = settings
+        .as_ref()
+        .map(|settings| string_list_from_config(settings, "cors_origins"))
+        .transpose()?
+        .unwrap_or_default();
+
+    let config_exclude = settings
+        .as_ref()
+        .map(|settings| string_list_from_config(settings, "exclude"))
+        .transpose()?
+        .unwrap_or_default();
+    let exclude = glob_set(&[exclude, config_exclude].concat())
+        .map_err(|err| anyhow!("Invalid --exclude: {}", err))?;
+    let include = glob_set(&include).map_err(|err| anyhow!("Invalid --include: {}", err))?;
+
+    let mut cors_origins = vec![];
+    for origin in cors_origin.iter().chain(&config_cors_origins) {
+        let origin =
+            parse_cors_origin(origin).map_err(|err| anyhow!("Invalid CORS origin: {}", err))?;
+
+        if !cors_origins.contains(&origin) {
+            cors_origins.push(origin);
+        }
+    }
+
+    let config_replacement_files = settings
+        .as_ref()
+        .map(replacement_files_from_config)
+        .unwrap_or_default();
+
+    let mut defaults = settings
+        .as_ref()
+        .map(|settings| {
+            from_config_builder(
+                settings,
+                placeholder_strict_format,
+                replacements_coerce_types,
+            )
+        })
+        .transpose()
+        .map_err(|err| {
+            anyhow!(
+                "Something went wrong while parsing the config file replacements: {}",
+                err
+            )
+        })?
+        .unwrap_or_default();
+
+    if let Some(prefix) = env_replacements {
+        let env_map = replacements_from_env_prefix(std::env::vars(), &prefix)
+            .map_err(|err| anyhow!("Invalid --env-replacements prefix: {}", err))?;
+
+        if env_map.is_empty() {
+            eprintln!(
+                "Warning: no environment variables starting with {} were found",
+                prefix
+            );
+        }
+
+        defaults.merge(env_map, MergeStrategy::KeepExisting);
+    }
+
+    let mut overrides = ReplacementsMap::default();
+
+    if let Some(near_cli_config) = near_cli_config {
+        overrides.insert("REPL_NETWORK".to_string(), near_cli_config.network)?;
+    }
+
+    if import_env_replacements {
+        overrides.merge(
+            replacements_from_env(std::env::vars()),
+            MergeStrategy::Override,
+        );
+    }
+
+    if let Some(replacements_inline) = replacements_inline {
+        let inline_map = parse_replacements(
+            &replacements_inline,
+            placeholder_strict_format,
+            replacements_coerce_types,
+        )
+        .map_err(|err| {
+            anyhow!(
+                "Something went wrong while parsing the inline replacements: {}",
+                err
+            )
+        })?;
+
+        overrides.merge(inline_map, MergeStrategy::Override);
+    }
+
+    if !replacements_kv.is_empty() {
+        let kv_map = replacements_from_kv(&replacements_kv)
+            .map_err(|err| anyhow!("Invalid --replacements-kv: {}", err))?;
+
+        overrides.merge(kv_map, MergeStrategy::Override);
+    }
+
+    let decryption_key = if replacements_encrypt_values {
+        let key = replacements_key
+            .or_else(|| std::env::var(DECRYPT_KEY_ENV).ok())
+            .ok_or(anyhow!("A key must be provided with --replacements-key or REPL_DECRYPT_KEY when using --replacements-encrypt-values"))?;
+
+        let key = parse_decryption_key(&key).map_err(|err| {
+            anyhow!(
+                "Something went wrong while decrypting the replacements: {}",
+                err
+            )
+        })?;
+
+        Some(key)
+    } else {
+        None
+    };
+
+    let replacements_sources = ReplacementsSources {
+        replacements_files: [config_replacement_files, replacements].concat(),
+        strict_format: placeholder_strict_format,
+        coerce_types: replacements_coerce_types,
+        comments_strip: replacements_comments_strip,
+        defaults,
+        overrides,
+        decryption_key,
+    };
+
+    let files_map = replacements_sources.read_files().await?;
+
+    // the template documents the replacements files alone, without the other sources
+    let replacements_template = replacements_template.map(|path| ReplacementsTemplate {
+        path,
+        replacements_files: replacements_sources.replacements_files.clone(),
+        replacements_map: files_map.clone(),
+    });
+
+    let replacements_map = replacements_sources.merge(files_map)?;
+
+    if replacements_validate_urls {
+        let invalid = invalid_url_values(&replacements_map);
+
+        if strict && !invalid.is_empty() {
+            bail!("{}", invalid.join("\n"));
+        }
+
+        for message in invalid {
+            eprintln!("Warning: {}", message);
+        }
+    }
+
+    let replacements_map = Arc::new(replacements_map);
+
+    let port_range = port_range
+        .map(|port_range| parse_port_range(&port_range))
+        .transpose()?;
+
+    let key_pattern = component_key_regex_validate
+        .map(|pattern| {
+            key_pattern(&pattern)
+                .map_err(|err| anyhow!("Invalid component key pattern {}: {}", pattern, err))
+        })
+        .transpose()?;
+
+    let size_budget = component_size_budget_file
+        .map(|path| SizeBudget::from_file(&path))
+        .transpose()?;
+
+    Ok(ResolvedConfig {
+        mode,
+        log_format,
+        color_output,
+        checks,
+        replacements_template,
+        replacements_schema_file: replacements_schema_generate,
+        replacements_generate,
+        accounts: account_paths,
+        remote_source,
+        tar_source,
+        test_mode,
+        host,
+        port,
+        port_range: port_range.clone(),
+        base_url,
+        web_engine,
+        parallel_accounts,
+        inject_account_metadata,
+        key_format,
+        replacements_map,
+        inject_repl_account: !no_repl_account_injection,
+        hash_algorithm: component_hash.then_some(component_hash_algorithm),
+        lint_rules: component_lint,
+        lint_strict,
+        max_component_age: component_age_check.map(Duration::from_secs),
+        max_nesting: component_max_nesting,
+        id_separator: component_id_separator,
+        strip_exports,
+        exclude: Arc::new(exclude),
+        include: Arc::new(include),
+        replace_in_keys,
+        max_file_size,
+        skip_oversized,
+        cache,
+        reload_token,
+        entry_point: component_entry_point,
+        replacements_audit_log,
+        log_replacements_skipped,
+        component_stats_file,
+        size_budget,
+        exit_after_first_request,
+        output_file,
+        output_ndjson,
+        serve_accounts_endpoint,
+        component_size_histogram,
+        component_tree,
+        cors_allow_headers,
+        cors_origins,
+        serve_on_ipv6,
+        watch,
+        watch_cooldown: watch_cooldown_ms.map(Duration::from_millis),
+        watch_coalesce_timeout: watch_coalesce_timeout.map(Duration::from_millis),
+        watch_emit_events,
+        watch_event_log_level,
+        watch_trigger_file,
+        replacements_watch,
+        replacements_sources,
+        reload_script,
+        key_pattern,
+        strict,
+        access_log,
+        access_log_max_size: access_log_max_size_mb.map(|size| size * 1024 * 1024),
+        metrics,
+    })
+}
+
+/// Lists the account paths as `"<path>" as account <account>`, one per line
+fn display_paths(accounts: &[AccountPath]) -> String {
+    accounts
+        .iter()
+        .map(|AccountPath { path, account, .. }| format!("{:?} as account {}", path, account))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Writes the files requested by `--replacements-template`, `--replacements-schema-generate` and
+/// `--replacements-generate`
+pub async fn write_replacement_files(config: &ResolvedConfig) -> Result<(), anyhow::Error> {
+    if let Some(ReplacementsTemplate {
+        path,
+        replacements_files,
+        replacements_map,
+    }) = &config.replacements_template
+    {
+        write_replacements_template(replacements_files, replacements_map, path)
+            .await
+            .map_err(|err| {
+                anyhow!(
+                    "Something went wrong while writing the replacements template: {}",
+                    err
+                )
+            })?;
+
+        println!("Wrote replacements template to {:?}", path);
+    }
+
+    if let Some(schema_path) = &config.replacements_schema_file {
+        write_replacements_schema(&config.replacements_map, schema_path)
+            .await
+            .map_err(|err| {
+                anyhow!(
+                    "Something went wrong while writing the replacements schema: {}",
+                    err
+                )
+            })?;
+
+        println!("Wrote replacements schema to {:?}", schema_path);
+    }
+
+    if let Some(generate_path) = &config.replacements_generate {
+        let files = server::dry_run(config.clone()).await?;
+        let keys = placeholder_keys(files.iter().map(|file| file.code.as_str()));
+
+        write_replacements_skeleton(&keys, generate_path)
+            .await
+            .map_err(|err| {
+                anyhow!(
+                    "Something went wrong while writing the replacements file: {}",
+                    err
+                )
+            })?;
+
+        println!(
+            "Wrote {} replacement keys to {:?}",
+            keys.len(),
+            generate_path
+        );
+    }
+
+    Ok(())
+}
+
+/// Writes the requested replacement files, then does what `config.mode` asks for, serving the
+/// components until the server stops by default. Returns whether it succeeded, `false` when the
+/// first request of `--exit-after-first-request` failed
+pub async fn run(config: ResolvedConfig) -> Result<bool, anyhow::Error> {
+    write_replacement_files(&config).await?;
+
+    let display_paths_str = display_paths(&config.accounts);
+
+    match config.mode.clone() {
+        RunMode::Check => {
+            let replacement_count = config.replacements_map.len();
+            let checks = config.checks.clone();
+            let file_list = server::check_components(config)
+                .await
+                .map_err(|err| anyhow!("Startup checks failed: {}", err))?;
+
+            println!(
+                "Loaded {} components with {} replacements from\n\n{}\n",
+                file_list.components.len(),
+                replacement_count,
+                display_paths_str
+            );
+            if !checks.is_empty() {
+                println!("Checked {}", checks.join(", "));
+            }
+            println!("All checks passed, not starting the server (--disable-serve)");
+        }
+        RunMode::DryRun => {
+            let output_ndjson = config.output_ndjson;
+            let color_output = config.color_output;
+            let files = server::dry_run(config).await?;
+
+            if output_ndjson {
+                let mut stdout = std::io::stdout().lock();
+
+                for file in &files {
+                    writeln!(stdout, "{}", ndjson_line(&file.key, &file.code))?;
+                }
+
+                return Ok(true);
+            }
+
+            let color = color_output && std::io::stdout().is_terminal();
+
+            for file in &files {
+                println!("{}\t{}", file.key, file.source_path.display());
+
+                if color_output {
+                    let code = if color {
+                        highlight_code(&file.code)
+                    } else {
+                        file.code.clone()
+                    };
+
+                    println!("{}\n", code.trim_end());
+                }
+            }
+
+            println!("\n{} components would be loaded", files.len());
+        }
+        RunMode::Export(output) => {
+            let file_list = export_components(config, &output).await.map_err(|err| {
+                anyhow!(
+                    "Something went wrong while exporting the components: {}",
+                    err
+                )
+            })?;
+
+            println!(
+                "Wrote {} components to {:?}\n\n{}",
+                file_list.components.len(),
+                output,
+                display_paths_str
+            );
+        }
+        RunMode::Serve => {
+            init_logging(config.log_format);
+
+            let port = config.port;
+            let port_range = config.port_range.clone();
+            let exit_after_first_request = config.exit_after_first_request;
+
+            let (server, join_handle) = run_server(config).await.map_err(|err| {
+                anyhow!("Something went wrong while starting the server: {}", err)
+            })?;
+
+            // on a line of its own so scripts starting the loader on an ephemeral port can read it
+            if port == 0 || port_range.is_some() {
+                println!("PORT={}", server.port());
+            }
+
+            let mut urls = format!("http://{}", server.address());
+            if server.serves_ipv6() {
+                urls.push_str(&format!(" and http://[::1]:{}", server.port()));
+            }
+
+            let extensions = COMPONENT_EXTENSIONS
+                .map(|extension| format!(".{extension}"))
+                .join("/");
+
+            println!(
+                "\nServing {} files on {}\n\n{}",
+                extensions, urls, display_paths_str
+            );
+
+            if exit_after_first_request {
+                let success = server.first_request().await;
+
+                server.shutdown();
+                join_handle.await?;
+
+                return Ok(success);
+            }
+
+            join_handle.await?;
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn resolve(args: &[&str]) -> Result<ResolvedConfig, anyhow::Error> {
+        resolve_config(Args::parse_from(
+            ["bos-loader"].iter().chain(args).collect::<Vec<_>>(),
+        ))
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_resolve_config() {
+        let config = resolve(&[
+            "alice.near",
+            "--path",
+            "./test/webengine",
+            "-w",
+            "--replacements",
+            "./test/replacements.json",
+            "--replacements-kv",
+            "REPL_PLACEHOLDER1=override",
+            "--port-range",
+            "3030-3040",
+        ])
+        .await
+        .unwrap();
+
+        assert_eq!(
+            config.accounts,
+            vec![AccountPath {
+                path: "./test/webengine".into(),
+                account: "alice.near".to_string(),
+                prefix: None,
+            }]
+        );
+        assert!(config.web_engine);
+        assert_eq!(config.key_format, WEB_ENGINE_KEY_FORMAT);
+        assert_eq!(config.port_range, Some(3030..=3040));
+        assert_eq!(
+            config.replacements_map.get("REPL_PLACEHOLDER1").unwrap(),
+            "override"
+        );
+        assert_eq!(
+            config.replacements_map.get("REPL_PLACEHOLDER2").unwrap(),
+            "value2"
+        );
+
+        let config = resolve(&[
+            "alice.near",
+            "-r",
+            "./test/replacements.json",
+            "-r",
+            "./test/replacements.override.json",
+        ])
+        .await
+        .unwrap();
+        assert_eq!(
+            config.replacements_map.get("REPL_PLACEHOLDER2").unwrap(),
+            "overridden"
+        );
+
+        let config = resolve(&["--accounts-json", "./test/accounts.json"])
+            .await
+            .unwrap();
+        assert_eq!(config.accounts.len(), 2);
+        assert_eq!(config.key_format, DEFAULT_KEY_FORMAT);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_config_run_mode() {
+        let config = resolve(&["alice.near"]).await.unwrap();
+        assert_eq!(config.mode, RunMode::Serve);
+        assert!(config.checks.is_empty());
+        assert!(config.replacements_template.is_none());
+
+        let config = resolve(&[
+            "alice.near",
+            "--disable-serve",
+            "--max-file-size",
+            "1024",
+            "--replacements-validate-urls",
+        ])
+        .await
+        .unwrap();
+        assert_eq!(config.mode, RunMode::Check);
+        assert_eq!(config.checks, vec!["replacement URLs", "file sizes"]);
+
+        let config = resolve(&["alice.near", "--dry-run", "--color-output"])
+            .await
+            .unwrap();
+        assert_eq!(config.mode, RunMode::DryRun);
+        assert!(config.color_output);
+
+        let config = resolve(&["alice.near", "-o", "components.json"])
+            .await
+            .unwrap();
+        assert_eq!(config.mode, RunMode::Export("components.json".into()));
+    }
+
+    #[tokio::test]
+    async fn test_run() {
+        let config = resolve(&["test.near", "--path", "./test/components", "--dry-run"])
+            .await
+            .unwrap();
+        assert!(run(config).await.unwrap());
+
+        // the first request fails to load the missing directory
+        let (port, listener) = crate::server::bind_random_port().unwrap();
+        drop(listener);
+        let run = tokio::spawn(run(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/missing".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            port,
+            exit_after_first_request: true,
+            ..Default::default()
+        }));
+
+        let url = format!("http://127.0.0.1:{port}");
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while reqwest::get(&url).await.is_err() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        assert!(!run.await.unwrap().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_write_replacement_files() {
+        let dir = std::env::temp_dir().join("bos-loader-test-write-replacement-files");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let template_path = dir.join("REPLACEMENTS.md");
+        let schema_path = dir.join("replacements.schema.json");
+
+        let config = resolve(&[
+            "alice.near",
+            "-r",
+            "./test/replacements.json",
+            "--replacements-kv",
+            "REPL_EXTRA=extra",
+            "--replacements-template",
+            template_path.to_str().unwrap(),
+            "--replacements-schema-generate",
+            schema_path.to_str().unwrap(),
+        ])
+        .await
+        .unwrap();
+
+        let template = config.replacements_template.as_ref().unwrap();
+        assert_eq!(
+            template.replacements_files,
+            vec![PathBuf::from("./test/replacements.json")]
+        );
+        assert!(template.replacements_map.get("REPL_EXTRA").is_none());
+
+        write_replacement_files(&config).await.unwrap();
+
+        let template = std::fs::read_to_string(&template_path).unwrap();
+        assert!(template.contains("REPL_PLACEHOLDER1"), "{template}");
+        assert!(!template.contains("REPL_EXTRA"), "{template}");
+
+        let schema = std::fs::read_to_string(&schema_path).unwrap();
+        assert!(schema.contains("REPL_EXTRA"), "{schema}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resolve_config_errors() {
+        let err = resolve(&[]).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Account ID must be provided when not using configuration file"
+        );
+
+        assert!(resolve(&["Alice.near"]).await.is_err());
+        assert!(resolve(&["alice.near", "--include", "["]).await.is_err());
+        assert!(resolve(&["alice.near", "--replacements-kv", "REPL_A"])
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_port_range() {
+        assert_eq!(parse_port_range("3030-3040").unwrap(), 3030..=3040);
+        assert_eq!(parse_port_range("8080-8080").unwrap(), 8080..=8080);
+
+        for invalid in ["3030", "3040-3030", "0-10", "a-b", "3030-70000", "-3030"] {
+            assert!(parse_port_range(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_glob_set() {
+        let exclude = glob_set(&[
+            "**/__tests__/**".to_string(),
+            "*.stories.jsx".to_string(),
+            "**/node_modules".to_string(),
+        ])
+        .unwrap();
+
+        assert!(exclude.is_match("__tests__/Foo.test.jsx"));
+        assert!(exclude.is_match("ui/__tests__/Foo.test.jsx"));
+        assert!(exclude.is_match("ui/Button.stories.jsx"));
+        assert!(exclude.is_match("node_modules"));
+        assert!(exclude.is_match("ui/node_modules"));
+        assert!(!exclude.is_match("ui/Button.jsx"));
+        assert!(glob_set(&["a[".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_parse_cors_origin() {
+        assert_eq!(
+            parse_cors_origin("https://near.org").unwrap(),
+            "https://near.org"
+        );
+        assert_eq!(
+            parse_cors_origin("HTTPS://Near.org:443/").unwrap(),
+            "https://near.org"
+        );
+        assert_eq!(
+            parse_cors_origin("http://localhost:3000").unwrap(),
+            "http://localhost:3000"
+        );
+
+        for invalid in [
+            "near.org",
+            "*",
+            "ftp://near.org",
+            "https://near.org/widgets",
+            "https://near.org?a=b",
+            "https://user@near.org",
+        ] {
+            assert!(parse_cors_origin(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_find_config_file() {
+        assert_eq!(
+            find_config_file(Path::new("./test")),
+            Path::new("./test/.bos-loader.toml")
+        );
+        assert_eq!(
+            find_config_file(Path::new("./test/json-config")),
+            Path::new("./test/json-config/.bos-loader.json")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_json_config_file() {
+        let ConfigFile::Settings(settings) =
+            load_config_file(&find_config_file(Path::new("./test/json-config")))
+                .await
+                .unwrap()
+        else {
+            panic!("expected the settings of a config object");
+        };
+
+        let account_paths = settings.get::<Vec<AccountPath>>("paths").unwrap();
+
+        assert_eq!(account_paths.len(), 1);
+        assert_eq!(account_paths[0].account, "near");
+        assert_eq!(account_paths[0].path, PathBuf::from("./components"));
+        assert_eq!(
+            string_list_from_config(&settings, "cors_origins").unwrap(),
+            vec!["https://near.org".to_string()]
+        );
+        assert_eq!(
+            from_config_builder(&settings, false, false)
+                .unwrap()
+                .get("REPL_API_URL")
+                .map(String::as_str),
+            Some("https://api.near.org")
+        );
+
+        let ConfigFile::Paths(account_paths) =
+            load_config_file(Path::new("./test/json-config-array/.bos-loader.json"))
+                .await
+                .unwrap()
+        else {
+            panic!("expected the paths of a config array");
+        };
+
+        assert_eq!(account_paths.len(), 1);
+        assert_eq!(account_paths[0].account, "near");
+    }
+
+    #[tokio::test]
+    async fn test_read_near_cli_config() {
+        assert_eq!(
+            read_near_cli_config(Path::new("./test/near-home"), None)
+                .await
+                .unwrap(),
+            Some(NearCliConfig {
+                network: "testnet".to_string(),
+                account: "alice.testnet".to_string(),
+            })
+        );
+        assert_eq!(
+            read_near_cli_config(Path::new("./test/near-home"), Some("mainnet".to_string()))
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    // TODO: add tests for config file multi-account setup
+
+    #[tokio::test]
+    async fn test_discover_workspace() {
+        let account_paths = discover_workspace(Path::new("./test/workspace"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            account_paths,
+            vec![
+                AccountPath {
+                    path: "./test/workspace/app/./src".into(),
+                    account: "app.near".to_string(),
+                    prefix: None,
+                },
+                AccountPath {
+                    path: "./test/workspace/packages/ui/./src".into(),
+                    account: "ui.near".to_string(),
+                    prefix: None,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,1800 @@
+use crate::{
+    accounts::check_prefix,
+    key::ComponentKey,
+    path::{ComponentPathResolver, KeyParts},
+    replacements::{replace_placeholders, ReplacementsMap, Substitution},
+};
+use anyhow::anyhow;
+use async_compression::tokio::bufread::GzipDecoder;
+use async_recursion::async_recursion;
+use clap::ValueEnum;
+use globset::GlobSet;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, LazyLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, BufReader},
+    sync::Mutex,
+};
+use tokio_stream::StreamExt;
+use tokio_tar::Archive;
+
+pub const DEFAULT_KEY_FORMAT: &str = "{account}/widget/{prefix}{name}";
+pub const WEB_ENGINE_KEY_FORMAT: &str = "{account}/{prefix}{name}";
+
+/// Joins directory and file name segments of nested component names, e.g. `Nested.Child`
+pub const DEFAULT_ID_SEPARATOR: &str = ".";
+
+/// Directory depth past which `load_components` warns about nesting
+pub const DEFAULT_MAX_NESTING: usize = 8;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum LintRule {
+    /// Warn on console.log
+    #[value(name = "no-console")]
+    Console,
+    /// Error on eval(
+    #[value(name = "no-eval")]
+    Eval,
+    /// Warn on .innerHTML
+    #[value(name = "no-inner-html")]
+    InnerHtml,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LintSeverity {
+    Warning,
+    Error,
+}
+
+impl LintRule {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LintRule::Console => "no-console",
+            LintRule::Eval => "no-eval",
+            LintRule::InnerHtml => "no-inner-html",
+        }
+    }
+
+    fn violated_by(&self, code: &str) -> bool {
+        match self {
+            LintRule::Console => code.contains("console.log"),
+            LintRule::Eval => code.contains("eval("),
+            LintRule::InnerHtml => code.contains(".innerHTML"),
+        }
+    }
+
+    fn severity(&self) -> LintSeverity {
+        match self {
+            LintRule::Console | LintRule::InnerHtml => LintSeverity::Warning,
+            LintRule::Eval => LintSeverity::Error,
+        }
+    }
+}
+
+/// Reports lint violations for a component, returns false if it should be excluded
+pub(crate) fn lint_component(key: &str, code: &str, rules: &[LintRule], strict: bool) -> bool {
+    let mut passed = true;
+
+    for rule in rules.iter().filter(|rule| rule.violated_by(code)) {
+        let severity = if strict {
+            LintSeverity::Error
+        } else {
+            rule.severity()
+        };
+
+        match severity {
+            LintSeverity::Warning => {
+                eprintln!("Warning: {} violates lint rule {}", key, rule.name())
+            }
+            LintSeverity::Error => {
+                eprintln!(
+                    "Error: {} violates lint rule {}, component will not be served",
+                    key,
+                    rule.name()
+                );
+                passed = false;
+            }
+        }
+    }
+
+    passed
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha512,
+    Blake3,
+    Md5,
+}
+
+impl HashAlgorithm {
+    /// Returns the hex encoded digest of `data`
+    pub fn digest(&self, data: &[u8]) -> String {
+        use sha2::Digest;
+
+        let to_hex = |bytes: &[u8]| bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+
+        match self {
+            HashAlgorithm::Sha256 => to_hex(&sha2::Sha256::digest(data)),
+            HashAlgorithm::Sha512 => to_hex(&sha2::Sha512::digest(data)),
+            HashAlgorithm::Blake3 => blake3::hash(data).to_hex().to_string(),
+            HashAlgorithm::Md5 => format!("{:x}", md5::compute(data)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileList {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry_point: Option<ComponentKey>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<FileListMetadata>,
+    /// Sorted distinct accounts of the component keys, with `--serve-accounts-endpoint`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accounts: Option<Vec<String>>,
+    pub components: HashMap<ComponentKey, ComponentCode>,
+}
+
+/// Fetches the components of a `FileList` served at `url`, e.g. by another bos-loader
+pub async fn fetch_remote_components(
+    url: &str,
+) -> Result<HashMap<ComponentKey, ComponentCode>, anyhow::Error> {
+    let response = reqwest::get(url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| anyhow!("Failed to fetch remote source {} \n Error: {:?}", url, err))?;
+
+    let file_list = response.json::<FileList>().await.map_err(|err| {
+        anyhow!(
+            "Invalid component list from remote source {} \n Error: {:?}",
+            url,
+            err
+        )
+    })?;
+
+    Ok(file_list.components)
+}
+
+/// Loads the component entries of a `.tar.gz` archive with the default key format and
+/// separator, see [`load_components_from_tar_with_options`]
+pub async fn load_components_from_tar(
+    archive_path: PathBuf,
+    account: &str,
+    replacements_map: Arc<ReplacementsMap>,
+) -> Result<HashMap<ComponentKey, ComponentCode>, anyhow::Error> {
+    load_components_from_tar_with_options(HandleRequestOptions {
+        path: archive_path,
+        account: account.to_string(),
+        replacements_map,
+        ..Default::default()
+    })
+    .await
+}
+
+/// Loads the component entries of the `.tar.gz` archive at `path`, deriving keys from their
+/// paths in the archive and processing their code like [`load_components`] does for files in a
+/// directory
+pub async fn load_components_from_tar_with_options(
+    HandleRequestOptions {
+        path: archive_path,
+        account,
+        prefix,
+        web_engine,
+        key_format,
+        replacements_map,
+        inject_repl_account,
+        hash_algorithm,
+        lint_rules,
+        lint_strict,
+        id_separator,
+        strip_exports,
+        include,
+        max_file_size,
+        skip_oversized,
+        ..
+    }: HandleRequestOptions,
+) -> Result<HashMap<ComponentKey, ComponentCode>, anyhow::Error> {
+    check_prefix(&prefix, &id_separator)?;
+
+    // entry paths are relative to the root of the archive
+    let resolver = ComponentPathResolver {
+        prefix,
+        key_format,
+        ..ComponentPathResolver::new("", &account, web_engine, &id_separator)
+    };
+
+    let file = fs::File::open(&archive_path)
+        .await
+        .map_err(|err| anyhow!("Failed to open file {:?} \n Error: {:?}", archive_path, err))?;
+    let mut archive = Archive::new(GzipDecoder::new(BufReader::new(file)));
+    let mut entries = archive.entries().map_err(|err| {
+        anyhow!(
+            "Could not read archive entries of {:?} \n Error: {:?}",
+            archive_path,
+            err
+        )
+    })?;
+
+    let mut components = HashMap::new();
+
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry.map_err(|err| {
+            anyhow!(
+                "Could not read archive entry of {:?} \n Error: {:?}",
+                archive_path,
+                err
+            )
+        })?;
+
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let entry_path = entry
+            .path()
+            .map_err(|err| {
+                anyhow!(
+                    "Invalid entry path in {:?} \n Error: {:?}",
+                    archive_path,
+                    err
+                )
+            })?
+            .into_owned();
+
+        let Some(KeyParts { key, .. }) = resolver.key_parts(&entry_path) else {
+            continue;
+        };
+        let key = ComponentKey::parse(&key)
+            .map_err(|err| anyhow!("Invalid key for entry {:?} \n Error: {}", entry_path, err))?;
+
+        if !include.is_empty() && !include.is_match(key.as_str()) {
+            continue;
+        }
+
+        if let Some(max_file_size) = max_file_size {
+            let size = entry.header().size().unwrap_or_default();
+
+            if size > max_file_size {
+                if skip_oversized {
+                    eprintln!(
+                        "Warning: skipping {:?}, its {} bytes exceed the maximum file size of {} bytes",
+                        entry_path, size, max_file_size
+                    );
+                    continue;
+                }
+
+                return Err(anyhow!(
+                    "Entry {:?} is {} bytes, more than the maximum file size of {} bytes",
+                    entry_path,
+                    size,
+                    max_file_size
+                ));
+            }
+        }
+
+        let mut code = String::new();
+        entry.read_to_string(&mut code).await.map_err(|err| {
+            anyhow!(
+                "Failed to read entry {:?} of {:?} \n Error: {:?}",
+                entry_path,
+                archive_path,
+                err
+            )
+        })?;
+
+        if strip_exports {
+            code = strip_module_exports(&code);
+        }
+
+        let (code, _) =
+            replace_placeholders(&code, &account, &replacements_map, inject_repl_account);
+
+        if !lint_component(&key, &code, &lint_rules, lint_strict) {
+            continue;
+        }
+
+        let hash = hash_algorithm.map(|algorithm| algorithm.digest(code.as_bytes()));
+
+        components.insert(
+            key,
+            ComponentCode {
+                code,
+                css: None,
+                hash,
+                url: None,
+            },
+        );
+    }
+
+    Ok(components)
+}
+
+/// Account the [`TEST_MODE_COMPONENTS`] are served as when none is given
+pub const TEST_MODE_ACCOUNT: &str = "test.near";
+
+/// Example components served by `--test-mode` instead of reading the filesystem, as
+/// `(path, code)` pairs
+pub const TEST_MODE_COMPONENTS: &[(&str, &str)] = &[
+    ("HelloWorld.jsx", "return <>Hello World</>;"),
+    (
+        "Greeting.tsx",
+        "return <Widget src=\"${REPL_ACCOUNT}/widget/HelloWorld\" />;",
+    ),
+    (
+        "Nested/Counter.jsx",
+        "const [count, setCount] = useState(0);\n\nreturn <button onClick={() => setCount(count + 1)}>{count}</button>;",
+    ),
+];
+
+/// Loads the [`TEST_MODE_COMPONENTS`] as `account`, deriving their keys like [`load_components`]
+pub fn load_test_mode_components(
+    account: &str,
+    key_format: &str,
+    id_separator: &str,
+    replacements_map: &ReplacementsMap,
+    inject_repl_account: bool,
+) -> Result<HashMap<ComponentKey, ComponentCode>, anyhow::Error> {
+    let mut components = HashMap::new();
+
+    let resolver = ComponentPathResolver {
+        key_format: key_format.to_string(),
+        ..ComponentPathResolver::new("", account, false, id_separator)
+    };
+
+    for (path, code) in TEST_MODE_COMPONENTS {
+        let KeyParts { key, .. } = resolver.key_parts(Path::new(path)).ok_or(anyhow!(
+            "Test mode component {} is not a component file",
+            path
+        ))?;
+        let key = ComponentKey::parse(&key).map_err(|err| {
+            anyhow!(
+                "Invalid key for test mode component {} \n Error: {}",
+                path,
+                err
+            )
+        })?;
+
+        let (code, _) = replace_placeholders(code, account, replacements_map, inject_repl_account);
+
+        components.insert(
+            key,
+            ComponentCode {
+                code,
+                css: None,
+                hash: None,
+                url: None,
+            },
+        );
+    }
+
+    Ok(components)
+}
+
+/// Describes where the served components were loaded from
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FileListMetadata {
+    pub accounts: Vec<AccountMetadata>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AccountMetadata {
+    pub account: String,
+    pub path: PathBuf,
+    pub component_count: usize,
+}
+
+/// Finds the key of the component called `name`, either a full key or the last path segment of one
+pub(crate) fn find_entry_point(
+    components: &HashMap<ComponentKey, ComponentCode>,
+    name: &str,
+) -> Option<ComponentKey> {
+    if let Some((key, _)) = components.get_key_value(name) {
+        return Some(key.clone());
+    }
+
+    let suffix = format!("/{name}");
+
+    components
+        .keys()
+        .filter(|key| key.ends_with(&suffix))
+        .min()
+        .cloned()
+}
+
+/// Compiles a pattern that component keys must match in full
+pub fn key_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^(?:{})$", pattern))
+}
+
+/// Returns the keys that don't match `pattern`, sorted
+pub(crate) fn keys_not_matching(
+    components: &HashMap<ComponentKey, ComponentCode>,
+    pattern: &Regex,
+) -> Vec<ComponentKey> {
+    let mut keys = components
+        .keys()
+        .filter(|key| !pattern.is_match(key))
+        .cloned()
+        .collect::<Vec<ComponentKey>>();
+    keys.sort();
+
+    keys
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ComponentCode {
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub css: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// Where the component is served individually, `<base url>/components/<key>`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AuditLogEntry {
+    pub component_key: ComponentKey,
+    #[serde(flatten)]
+    pub substitution: Substitution,
+}
+
+/// Size and timing of a loaded component
+#[derive(Clone, Debug)]
+pub struct ComponentStats {
+    pub key: ComponentKey,
+    pub source_path: PathBuf,
+    pub raw_bytes: usize,
+    pub post_replacement_bytes: usize,
+    pub load_duration: Duration,
+    pub mtime: Option<SystemTime>,
+}
+
+/// Details collected while loading components, shared across all accounts of a load
+#[derive(Clone, Debug, Default)]
+pub struct LoadReport {
+    pub(crate) audit_log: Vec<AuditLogEntry>,
+    pub(crate) stats: Vec<ComponentStats>,
+    /// Files and directories skipped by `--exclude`, directories aren't walked
+    pub(crate) excluded: Vec<PathBuf>,
+}
+
+impl LoadReport {
+    /// Adds the details of `other`, e.g. an account served from the cache
+    pub(crate) fn extend(&mut self, other: &LoadReport) {
+        self.audit_log.extend(other.audit_log.iter().cloned());
+        self.stats.extend(other.stats.iter().cloned());
+        self.excluded.extend(other.excluded.iter().cloned());
+    }
+}
+
+/// Overwrites `path` with one JSON line per substitution of the load
+pub(crate) async fn write_audit_log(
+    path: &Path,
+    entries: &[AuditLogEntry],
+) -> Result<(), anyhow::Error> {
+    let mut entries = entries.to_vec();
+    entries.sort_by(|a, b| {
+        (&a.component_key, &a.substitution.placeholder)
+            .cmp(&(&b.component_key, &b.substitution.placeholder))
+    });
+
+    let mut contents = String::new();
+    for entry in entries {
+        contents.push_str(&serde_json::to_string(&entry)?);
+        contents.push('\n');
+    }
+
+    fs::write(path, contents)
+        .await
+        .map_err(|err| anyhow!("Failed to write file {:?} \n Error: {:?}", path, err))
+}
+
+/// Quotes a CSV field when it contains a delimiter, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Builds a CSV table with one row per component, sorted by key
+pub(crate) fn component_stats_csv(stats: &[ComponentStats]) -> String {
+    let mut stats = stats.iter().collect::<Vec<&ComponentStats>>();
+    stats.sort_by(|a, b| a.key.cmp(&b.key));
+
+    let mut contents =
+        String::from("key,source_path,raw_bytes,post_replacement_bytes,load_duration_us,mtime\n");
+
+    for stat in stats {
+        let mtime = stat
+            .mtime
+            .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+            .map(|mtime| mtime.as_secs().to_string())
+            .unwrap_or_default();
+
+        contents.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            csv_field(&stat.key),
+            csv_field(&stat.source_path.to_string_lossy()),
+            stat.raw_bytes,
+            stat.post_replacement_bytes,
+            stat.load_duration.as_micros(),
+            mtime
+        ));
+    }
+
+    contents
+}
+
+/// Overwrites `path` with the CSV statistics of the load
+pub(crate) async fn write_component_stats(
+    path: &Path,
+    stats: &[ComponentStats],
+) -> Result<(), anyhow::Error> {
+    fs::write(path, component_stats_csv(stats))
+        .await
+        .map_err(|err| anyhow!("Failed to write file {:?} \n Error: {:?}", path, err))
+}
+
+/// A level of the component tree, keyed by segment name
+#[derive(Default)]
+struct ComponentTreeNode(BTreeMap<String, ComponentTreeNode>);
+
+impl ComponentTreeNode {
+    fn write(&self, depth: usize, output: &mut String) {
+        for (name, child) in &self.0 {
+            output.push_str(&format!("{}{}\n", "  ".repeat(depth), name));
+            child.write(depth + 1, output);
+        }
+    }
+}
+
+/// Formats the component keys as a tree indented by two spaces per level, splitting keys on `/`
+/// and the segments after the account on `id_separator`
+pub(crate) fn format_component_tree<'a>(
+    keys: impl IntoIterator<Item = &'a ComponentKey>,
+    id_separator: &str,
+) -> String {
+    let mut root = ComponentTreeNode::default();
+
+    for key in keys {
+        let mut segments = key.as_str().split('/');
+        let account = segments.next().unwrap_or_default();
+
+        let mut node = root.0.entry(account.to_string()).or_default();
+        for name in segments.flat_map(|segment| segment.split(id_separator)) {
+            node = node.0.entry(name.to_string()).or_default();
+        }
+    }
+
+    let mut output = String::new();
+    root.write(0, &mut output);
+
+    output
+}
+
+/// Upper bounds in bytes of the component size histogram buckets, the last bucket is unbounded
+const SIZE_HISTOGRAM_BOUNDS: [usize; 3] = [1024, 10 * 1024, 50 * 1024];
+const SIZE_HISTOGRAM_LABELS: [&str; 4] = ["0-1KB", "1KB-10KB", "10KB-50KB", "50KB+"];
+
+/// Counts the components in each size bucket, by length of their code
+pub(crate) fn size_histogram(components: &HashMap<ComponentKey, ComponentCode>) -> [usize; 4] {
+    let mut buckets = [0; 4];
+
+    for component in components.values() {
+        let bucket = SIZE_HISTOGRAM_BOUNDS
+            .iter()
+            .position(|bound| component.code.len() < *bound)
+            .unwrap_or(SIZE_HISTOGRAM_BOUNDS.len());
+
+        buckets[bucket] += 1;
+    }
+
+    buckets
+}
+
+/// Renders the size histogram as one bar per bucket, scaled to the largest bucket
+pub(crate) fn format_size_histogram(buckets: &[usize; 4]) -> String {
+    const BAR_WIDTH: usize = 40;
+    let max = buckets.iter().copied().max().unwrap_or_default().max(1);
+
+    SIZE_HISTOGRAM_LABELS
+        .iter()
+        .zip(buckets)
+        .map(|(label, count)| {
+            format!(
+                "{:>9} | {:<width$} {}\n",
+                label,
+                "#".repeat(count * BAR_WIDTH / max),
+                count,
+                width = BAR_WIDTH
+            )
+        })
+        .collect()
+}
+
+pub struct HandleRequestOptions {
+    pub path: PathBuf,
+    pub account: String,
+    /// Prepended to the `{prefix}` of every key, validated with [`check_prefix`]
+    pub prefix: String,
+    pub web_engine: bool,
+    pub key_format: String,
+    pub replacements_map: Arc<ReplacementsMap>,
+    pub inject_repl_account: bool,
+    pub hash_algorithm: Option<HashAlgorithm>,
+    pub lint_rules: Vec<LintRule>,
+    pub lint_strict: bool,
+    pub max_component_age: Option<Duration>,
+    pub max_nesting: usize,
+    pub id_separator: String,
+    pub strip_exports: bool,
+    /// Matched against the path of every file and directory relative to `path`
+    pub exclude: Arc<GlobSet>,
+    /// Matched against the final component keys, all components are loaded when empty
+    pub include: Arc<GlobSet>,
+    /// Also replace placeholders in the keys, e.g. of `${REPL_ACCOUNT}` directories
+    pub replace_in_keys: bool,
+    /// Files larger than this many bytes fail the load, or are skipped with `skip_oversized`
+    pub max_file_size: Option<u64>,
+    pub skip_oversized: bool,
+    pub report: Arc<Mutex<LoadReport>>,
+}
+
+impl Default for HandleRequestOptions {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("."),
+            account: String::new(),
+            prefix: String::new(),
+            web_engine: false,
+            key_format: DEFAULT_KEY_FORMAT.to_string(),
+            replacements_map: Arc::new(ReplacementsMap::default()),
+            inject_repl_account: true,
+            hash_algorithm: None,
+            lint_rules: vec![],
+            lint_strict: false,
+            max_component_age: None,
+            max_nesting: DEFAULT_MAX_NESTING,
+            id_separator: DEFAULT_ID_SEPARATOR.to_string(),
+            strip_exports: false,
+            exclude: Arc::new(GlobSet::empty()),
+            include: Arc::new(GlobSet::empty()),
+            replace_in_keys: false,
+            max_file_size: None,
+            skip_oversized: false,
+            report: Arc::new(Mutex::new(LoadReport::default())),
+        }
+    }
+}
+
+pub async fn handle_request(
+    HandleRequestOptions {
+        path,
+        account,
+        prefix,
+        web_engine,
+        key_format,
+        replacements_map,
+        inject_repl_account,
+        hash_algorithm,
+        lint_rules,
+        lint_strict,
+        max_component_age,
+        max_nesting,
+        id_separator,
+        strip_exports,
+        exclude,
+        include,
+        replace_in_keys,
+        max_file_size,
+        skip_oversized,
+        report,
+    }: HandleRequestOptions,
+) -> Result<Arc<Mutex<HashMap<ComponentKey, ComponentCode>>>, anyhow::Error> {
+    check_prefix(&prefix, &id_separator)?;
+
+    let components = Arc::new(Mutex::new(HashMap::new()));
+
+    let resolver = ComponentPathResolver {
+        prefix,
+        key_format,
+        ..ComponentPathResolver::new(path.clone(), &account, web_engine, &id_separator)
+    };
+
+    load_components(LoadComponentsOptions {
+        resolver: Arc::new(resolver),
+        path,
+        web_engine,
+        components: components.clone(),
+        replacements_map,
+        inject_repl_account,
+        hash_algorithm,
+        lint_rules,
+        lint_strict,
+        max_component_age,
+        max_nesting,
+        depth: 0,
+        strip_exports,
+        exclude,
+        include,
+        replace_in_keys,
+        max_file_size,
+        skip_oversized,
+        report,
+    })
+    .await?;
+
+    Ok(components)
+}
+
+/// Extensions of the files loaded as components
+pub(crate) const COMPONENT_EXTENSIONS: [&str; 4] = ["jsx", "tsx", "js", "ts"];
+
+/// Splits a component file name into its name, the dot separated parts before the extension
+/// joined with `id_separator`, and its extension. Files without a [`COMPONENT_EXTENSIONS`]
+/// extension give `None`
+pub(crate) fn component_file_name<'a>(
+    file_name: &'a str,
+    id_separator: &str,
+) -> Option<(String, &'a str)> {
+    let mut file_name_parts: Vec<&str> = file_name.split('.').collect();
+
+    let extension = file_name_parts.pop().unwrap_or_default();
+    if !COMPONENT_EXTENSIONS.contains(&extension) {
+        return None;
+    }
+
+    Some((file_name_parts.join(id_separator), extension))
+}
+
+/// How the key of a component file is derived, see [`explain_key`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyExplanation {
+    pub source_path: PathBuf,
+    pub prefix: String,
+    pub name: String,
+    pub id_separator: String,
+    pub key_format: String,
+    /// What joins the account and the prefix, `/widget/` by default and `/` with `-w`. `None` for
+    /// a `--component-key-format` that doesn't put them next to each other
+    pub join_string: Option<String>,
+    pub key: String,
+}
+
+impl std::fmt::Display for KeyExplanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Source path: {}", self.source_path.display())?;
+        writeln!(f, "Prefix:      {:?}", self.prefix)?;
+        writeln!(f, "Name:        {:?}", self.name)?;
+        writeln!(f, "Separator:   {:?}", self.id_separator)?;
+        writeln!(f, "Key format:  {}", self.key_format)?;
+        match &self.join_string {
+            Some(join_string) => writeln!(f, "Join string: {:?}", join_string)?,
+            None => writeln!(f, "Join string: none, set by the key format")?,
+        }
+        write!(f, "Key:         {}", self.key)
+    }
+}
+
+/// Derives the key `load_components` would give `file` when loading `root` as `account`
+pub fn explain_key(
+    root: &Path,
+    file: &Path,
+    account: &str,
+    key_format: &str,
+    id_separator: &str,
+) -> Result<KeyExplanation, anyhow::Error> {
+    // `test/Foo.jsx` is inside `.` and `./test` as much as `./test/Foo.jsx` is
+    let (root_path, file_path) = match (root.canonicalize(), file.canonicalize()) {
+        (Ok(root_path), Ok(file_path)) => (root_path, file_path),
+        _ => (without_cur_dir(root), without_cur_dir(file)),
+    };
+
+    if !file_path.starts_with(&root_path) {
+        return Err(anyhow!(
+            "File {:?} is not inside the component directory {:?}",
+            file,
+            root
+        ));
+    }
+
+    let resolver = ComponentPathResolver {
+        key_format: key_format.to_string(),
+        ..ComponentPathResolver::new(root_path, account, false, id_separator)
+    };
+    let KeyParts {
+        prefix, name, key, ..
+    } = resolver.key_parts(&file_path).ok_or(anyhow!(
+        "File {:?} is not a .jsx, .tsx, .js or .ts component",
+        file
+    ))?;
+
+    Ok(KeyExplanation {
+        source_path: file.to_path_buf(),
+        prefix,
+        name,
+        id_separator: id_separator.to_string(),
+        key_format: key_format.to_string(),
+        join_string: resolver.join_string().map(str::to_string),
+        key,
+    })
+}
+
+/// `path` without its `.` components
+fn without_cur_dir(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+pub(crate) fn format_component_key(
+    key_format: &str,
+    account: &str,
+    prefix: &str,
+    name: &str,
+    ext: &str,
+) -> String {
+    key_format
+        .replace("{account}", account)
+        .replace("{prefix}", prefix)
+        .replace("{name}", name)
+        .replace("{ext}", ext)
+}
+
+/// `export` keywords in front of top-level declarations, which are kept
+static EXPORTED_DECLARATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?m)^export\s+(?:default\s+)?((?:async\s+)?function\b|class\b|const\b|let\b|var\b)",
+    )
+    .unwrap()
+});
+
+/// Top-level `export default <name>;` and `export { ... }` statements, which are removed. Only a
+/// default export of a bare name on a single line is a statement of its own
+static EXPORT_STATEMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?m)^export\s+(?:default\s+[\w$]+[ \t]*;?[ \t]*$|\{[^}]*\}(?:\s*from\s*["'][^"']*["'])?\s*;?)[ \t]*(?:\r?\n)?"#)
+        .unwrap()
+});
+
+/// The `export default` in front of any other top-level expression, e.g. a multi-line
+/// `export default (props) => {`, which leaves the expression in place
+static EXPORT_DEFAULT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^export\s+default\s+").unwrap());
+
+/// Removes ES module exports, which BOS components can't have, keeping exported declarations
+/// and expressions
+pub(crate) fn strip_module_exports(code: &str) -> String {
+    let code = EXPORTED_DECLARATION.replace_all(code, "$1");
+    let code = EXPORT_STATEMENT.replace_all(&code, "");
+
+    EXPORT_DEFAULT.replace_all(&code, "").into_owned()
+}
+
+/// Whether a file modified at `modified` is older than `max_age`, files from the future are never stale
+pub(crate) fn is_stale(modified: SystemTime, max_age: Duration) -> bool {
+    modified.elapsed().map(|age| age > max_age).unwrap_or(false)
+}
+
+pub(crate) struct LoadComponentsOptions {
+    /// Derives the keys of the files, its `base_path` is the account directory `exclude`
+    /// patterns are relative to
+    resolver: Arc<ComponentPathResolver>,
+    path: PathBuf,
+    web_engine: bool,
+    components: Arc<Mutex<HashMap<ComponentKey, ComponentCode>>>,
+    replacements_map: Arc<ReplacementsMap>,
+    inject_repl_account: bool,
+    hash_algorithm: Option<HashAlgorithm>,
+    lint_rules: Vec<LintRule>,
+    lint_strict: bool,
+    max_component_age: Option<Duration>,
+    max_nesting: usize,
+    depth: usize,
+    strip_exports: bool,
+    exclude: Arc<GlobSet>,
+    include: Arc<GlobSet>,
+    replace_in_keys: bool,
+    max_file_size: Option<u64>,
+    skip_oversized: bool,
+    report: Arc<Mutex<LoadReport>>,
+}
+
+#[async_recursion]
+pub(crate) async fn load_components(
+    LoadComponentsOptions {
+        resolver,
+        path,
+        web_engine,
+        components,
+        replacements_map,
+        inject_repl_account,
+        hash_algorithm,
+        lint_rules,
+        lint_strict,
+        max_component_age,
+        max_nesting,
+        depth,
+        strip_exports,
+        exclude,
+        include,
+        replace_in_keys,
+        max_file_size,
+        skip_oversized,
+        report,
+    }: LoadComponentsOptions,
+) -> Result<(), anyhow::Error> {
+    let mut paths = fs::read_dir(path.clone())
+        .await
+        .map_err(|err| anyhow!("Could not read directory {:?} \n Error: {:?}", path, err))?;
+
+    while let Some(directory_entry) = paths.next_entry().await.map_err(|err| {
+        anyhow!(
+            "Could not read directory entries for path {:?} \n Error: {:?}",
+            path,
+            err
+        )
+    })? {
+        let file_path = directory_entry.path();
+
+        if !exclude.is_empty()
+            && exclude.is_match(
+                file_path
+                    .strip_prefix(&resolver.base_path)
+                    .unwrap_or(&file_path),
+            )
+        {
+            report.lock().await.excluded.push(file_path);
+            continue;
+        }
+
+        if directory_entry
+            .file_type()
+            .await
+            .map_err(|err| {
+                anyhow!(
+                    "Could not get file type from path {:?} \n Error: {:?}",
+                    file_path,
+                    err
+                )
+            })?
+            .is_dir()
+        {
+            // warn once, at the first directory nested deeper than allowed
+            if depth == max_nesting {
+                eprintln!(
+                    "Warning: {:?} is nested more than {} directories deep",
+                    file_path, max_nesting
+                );
+            }
+
+            load_components(LoadComponentsOptions {
+                resolver: resolver.clone(),
+                path: file_path,
+                web_engine,
+                components: components.clone(),
+                replacements_map: replacements_map.clone(),
+                inject_repl_account,
+                hash_algorithm,
+                lint_rules: lint_rules.clone(),
+                lint_strict,
+                max_component_age,
+                max_nesting,
+                depth: depth + 1,
+                strip_exports,
+                exclude: exclude.clone(),
+                include: include.clone(),
+                replace_in_keys,
+                max_file_size,
+                skip_oversized,
+                report: report.clone(),
+            })
+            .await?;
+
+            continue;
+        }
+
+        let Some(KeyParts { mut key, .. }) = resolver.key_parts(&file_path) else {
+            continue;
+        };
+        let account = &resolver.account;
+
+        if replace_in_keys {
+            let (replaced_key, _) =
+                replace_placeholders(&key, account, &replacements_map, inject_repl_account);
+            let account_of = |key: &str| key.split('/').next().unwrap_or_default().to_string();
+
+            if account_of(&replaced_key) != account_of(&key) {
+                eprintln!(
+                    "Warning: replacing placeholders in the key {} changes its account, it is served as {}",
+                    key, replaced_key
+                );
+            }
+
+            key = replaced_key;
+        }
+
+        let key = ComponentKey::parse(&key)
+            .map_err(|err| anyhow!("Invalid key for file {:?} \n Error: {}", file_path, err))?;
+
+        if !include.is_empty() && !include.is_match(key.as_str()) {
+            continue;
+        }
+
+        if let Some(max_file_size) = max_file_size {
+            let size = fs::metadata(&file_path)
+                .await
+                .map(|metadata| metadata.len())
+                .map_err(|err| {
+                    anyhow!(
+                        "Failed to read size of file {:?} \n Error: {:?}",
+                        file_path,
+                        err
+                    )
+                })?;
+
+            if size > max_file_size {
+                if skip_oversized {
+                    eprintln!(
+                        "Warning: skipping {:?}, its {} bytes exceed the maximum file size of {} bytes",
+                        file_path, size, max_file_size
+                    );
+                    continue;
+                }
+
+                return Err(anyhow!(
+                    "File {:?} is {} bytes, more than the maximum file size of {} bytes",
+                    file_path,
+                    size,
+                    max_file_size
+                ));
+            }
+        }
+
+        if let Some(max_component_age) = max_component_age {
+            let modified = fs::metadata(&file_path)
+                .await
+                .and_then(|metadata| metadata.modified())
+                .map_err(|err| {
+                    anyhow!(
+                        "Failed to read modification time of file {:?} \n Error: {:?}",
+                        file_path,
+                        err
+                    )
+                })?;
+
+            if is_stale(modified, max_component_age) {
+                eprintln!(
+                    "Warning: {} was last modified more than {} seconds ago",
+                    key,
+                    max_component_age.as_secs()
+                );
+            }
+        }
+
+        let started = Instant::now();
+
+        // read code
+        let mut code = String::new();
+        let mut file = fs::File::open(&file_path)
+            .await
+            .map_err(|err| anyhow!("Failed to open file {:?} \n Error: {:?}", file_path, err))?;
+
+        file.read_to_string(&mut code)
+            .await
+            .map_err(|err| anyhow!("Failed to read file {:?} \n Error: {:?}", file_path, err))?;
+
+        let raw_bytes = code.len();
+        let mtime = file
+            .metadata()
+            .await
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        if strip_exports {
+            code = strip_module_exports(&code);
+        }
+
+        let (replaced_code, replacement_stats) = replace_placeholders(
+            &code,
+            account,
+            &replacements_map.clone(),
+            inject_repl_account,
+        );
+        code = replaced_code;
+
+        if !lint_component(&key, &code, &lint_rules, lint_strict) {
+            continue;
+        }
+
+        // read css
+        let css: Option<String> = if web_engine {
+            let css_path = file_path.with_extension("module.css");
+            if css_path.exists() {
+                let mut css_file = fs::File::open(&css_path).await.map_err(|err| {
+                    anyhow!("Failed to open file {:?} \n Error: {:?}", css_path, err)
+                })?;
+
+                let mut read_css = String::new();
+                css_file
+                    .read_to_string(&mut read_css)
+                    .await
+                    .map_err(|err| {
+                        anyhow!("Failed to read file {:?} \n Error: {:?}", css_path, err)
+                    })?;
+
+                Some(read_css)
+            } else {
+                Some(String::from(""))
+            }
+        } else {
+            None
+        };
+
+        let hash = hash_algorithm.map(|algorithm| algorithm.digest(code.as_bytes()));
+
+        {
+            let mut report = report.lock().await;
+            report
+                .audit_log
+                .extend(
+                    replacement_stats
+                        .substitutions
+                        .into_iter()
+                        .map(|substitution| AuditLogEntry {
+                            component_key: key.clone(),
+                            substitution,
+                        }),
+                );
+            report.stats.push(ComponentStats {
+                key: key.clone(),
+                source_path: file_path.clone(),
+                raw_bytes,
+                post_replacement_bytes: code.len(),
+                load_duration: started.elapsed(),
+                mtime,
+            });
+        }
+
+        components.lock().await.insert(
+            key,
+            ComponentCode {
+                code,
+                css: if web_engine { css } else { None },
+                hash,
+                url: None,
+            },
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_basic_web_engine_components() {
+        let path: PathBuf = "./test/webengine".into();
+        let account = "test.near".to_string();
+        let web_engine = true;
+        let replacements_map = Arc::new(ReplacementsMap::default());
+
+        let components = handle_request(HandleRequestOptions {
+            path,
+            account,
+            web_engine,
+            key_format: WEB_ENGINE_KEY_FORMAT.to_string(),
+            replacements_map,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let components_lock = components.lock().await;
+
+        assert_eq!(
+            components_lock.get("test.near/WithStyle"),
+            Some(&ComponentCode {
+                code: String::from(
+                    "import s from \"./WithStyle.module.css\";\n\ntype Props = {\n  message?: string;\n};\n\nfunction WithStyle({ message = \"Hello!\" }: Props) {\n  return (\n    <div className={s.wrapper}>\n      <p>{message}</p>\n    </div>\n  );\n}\n\nexport default WithStyle as BWEComponent<Props>;\n"
+                ),
+                css: Some(String::from(".wrapper {\n  color: rebeccapurple;\n}")),
+                hash: None,
+                url: None,
+            })
+        );
+        assert_eq!(
+            components_lock.get("test.near/NoStyle"),
+            Some(&ComponentCode {
+                code: String::from(
+                    "type Props = {\n  message?: string;\n};\n\nfunction NoStyle({ message = \"Hello!\" }: Props) {\n  return (\n    <div>\n      <p>{message}</p>\n    </div>\n  );\n}\n\nexport default NoStyle as BWEComponent<Props>;\n"
+                ),
+                css: Some(String::from("")),
+                hash: None,
+                url: None,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_custom_component_key_format() {
+        let components = handle_request(HandleRequestOptions {
+            path: "./test/components".into(),
+            account: "test.near".to_string(),
+            key_format: "{account}/component/{prefix}{name}.{ext}".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let components_lock = components.lock().await;
+
+        assert!(components_lock.contains_key("test.near/component/Posts.jsx"));
+        assert!(components_lock.contains_key("test.near/component/Sub2.Sub2a.c.jsx"));
+        assert!(components_lock.contains_key("test.near/component/ts.tsx"));
+    }
+
+    #[tokio::test]
+    async fn test_plain_js_and_ts_components() {
+        let components = handle_request(HandleRequestOptions {
+            path: "./test/plain".into(),
+            account: "test.near".to_string(),
+            key_format: "{account}/widget/{prefix}{name}.{ext}".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let components_lock = components.lock().await;
+
+        assert_eq!(components_lock.len(), 2);
+        assert_eq!(
+            components_lock["test.near/widget/Plain.js"].code,
+            "return <>Plain</>;\n"
+        );
+        assert!(components_lock.contains_key("test.near/widget/Nested.Typed.ts"));
+    }
+
+    #[tokio::test]
+    async fn test_exclude_components() {
+        let mut exclude = globset::GlobSetBuilder::new();
+        for pattern in ["**/__tests__/**", "*.stories.jsx", "**/node_modules"] {
+            exclude.add(globset::Glob::new(pattern).unwrap());
+        }
+
+        let report = Arc::new(Mutex::new(LoadReport::default()));
+        let components = handle_request(HandleRequestOptions {
+            path: "./test/exclude".into(),
+            account: "test.near".to_string(),
+            exclude: Arc::new(exclude.build().unwrap()),
+            report: report.clone(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let mut keys = components
+            .lock()
+            .await
+            .keys()
+            .map(ToString::to_string)
+            .collect::<Vec<String>>();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec!["test.near/widget/Foo", "test.near/widget/ui.Button"]
+        );
+
+        let mut excluded = report.lock().await.excluded.clone();
+        excluded.sort();
+        assert_eq!(
+            excluded,
+            vec![
+                PathBuf::from("./test/exclude/Foo.stories.jsx"),
+                PathBuf::from("./test/exclude/__tests__/Foo.test.jsx"),
+                PathBuf::from("./test/exclude/node_modules"),
+                PathBuf::from("./test/exclude/ui/Button.stories.jsx"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_include_components() {
+        let mut include = globset::GlobSetBuilder::new();
+        include.add(globset::Glob::new("test.near/widget/ui.*").unwrap());
+
+        let components = handle_request(HandleRequestOptions {
+            path: "./test/exclude".into(),
+            account: "test.near".to_string(),
+            include: Arc::new(include.build().unwrap()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let mut keys = components
+            .lock()
+            .await
+            .keys()
+            .map(ToString::to_string)
+            .collect::<Vec<String>>();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                "test.near/widget/ui.Button",
+                "test.near/widget/ui.Button.stories"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replace_in_keys() {
+        let mut replacements_map = ReplacementsMap::default();
+        replacements_map
+            .insert("REPL_SCOPE".to_string(), "ui".to_string())
+            .unwrap();
+
+        let options = |replace_in_keys| HandleRequestOptions {
+            path: "./test/replace-keys".into(),
+            account: "test.near".to_string(),
+            replacements_map: Arc::new(replacements_map.clone()),
+            replace_in_keys,
+            ..Default::default()
+        };
+        let keys = |components: HashMap<ComponentKey, ComponentCode>| {
+            let mut keys = components
+                .into_keys()
+                .map(|key| key.to_string())
+                .collect::<Vec<String>>();
+            keys.sort();
+
+            keys
+        };
+
+        let components = handle_request(options(true)).await.unwrap();
+        assert_eq!(
+            keys(components.lock().await.clone()),
+            vec![
+                "test.near/widget/test.near.Feed",
+                "test.near/widget/ui.Button"
+            ]
+        );
+
+        let components = handle_request(options(false)).await.unwrap();
+        assert_eq!(
+            keys(components.lock().await.clone()),
+            vec![
+                "test.near/widget/${REPL_ACCOUNT}.Feed",
+                "test.near/widget/${REPL_SCOPE}.Button"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_max_file_size() {
+        let options = |max_file_size, skip_oversized| HandleRequestOptions {
+            path: "./test/max-file-size".into(),
+            account: "test.near".to_string(),
+            inject_repl_account: false,
+            max_file_size,
+            skip_oversized,
+            ..Default::default()
+        };
+
+        let components = handle_request(options(None, false)).await.unwrap();
+        assert_eq!(components.lock().await.len(), 2);
+
+        // Small.jsx is 19 bytes, Large.jsx is over 100
+        let err = handle_request(options(Some(64), false)).await.unwrap_err();
+        assert!(err.to_string().contains("Large.jsx"), "{err}");
+        assert!(
+            err.to_string().contains("maximum file size of 64 bytes"),
+            "{err}"
+        );
+
+        let components = handle_request(options(Some(64), true)).await.unwrap();
+        assert_eq!(
+            components.lock().await.keys().cloned().collect::<Vec<_>>(),
+            vec![ComponentKey::parse("test.near/widget/Small").unwrap()]
+        );
+
+        // files of exactly the maximum size still load
+        let components = handle_request(options(Some(112), false)).await.unwrap();
+        assert_eq!(components.lock().await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_component_id_separator() {
+        let components = handle_request(HandleRequestOptions {
+            path: "./test/components".into(),
+            account: "test.near".to_string(),
+            id_separator: "/".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let components_lock = components.lock().await;
+
+        assert!(components_lock.contains_key("test.near/widget/Sub2/Sub2a/c"));
+        assert!(components_lock.contains_key("test.near/widget/Posts"));
+    }
+
+    #[tokio::test]
+    async fn test_account_prefix() {
+        let options = |prefix: &str| HandleRequestOptions {
+            path: "./test/components/Sub2".into(),
+            account: "test.near".to_string(),
+            prefix: prefix.to_string(),
+            ..Default::default()
+        };
+
+        let components = handle_request(options("Vendor.")).await.unwrap();
+        let components_lock = components.lock().await;
+
+        assert!(components_lock.contains_key("test.near/widget/Vendor.b"));
+        assert!(components_lock.contains_key("test.near/widget/Vendor.Sub2a.c"));
+
+        assert!(handle_request(options(".Vendor.")).await.is_err());
+    }
+
+    #[test]
+    fn test_load_test_mode_components() {
+        let components = load_test_mode_components(
+            "alice.near",
+            DEFAULT_KEY_FORMAT,
+            DEFAULT_ID_SEPARATOR,
+            &ReplacementsMap::default(),
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(components.len(), TEST_MODE_COMPONENTS.len());
+        assert!(components.contains_key("alice.near/widget/HelloWorld"));
+        assert!(components.contains_key("alice.near/widget/Nested.Counter"));
+        assert_eq!(
+            components["alice.near/widget/Greeting"].code,
+            "return <Widget src=\"alice.near/widget/HelloWorld\" />;"
+        );
+    }
+
+    #[test]
+    fn test_hash_algorithm_digest() {
+        assert_eq!(
+            HashAlgorithm::Sha256.digest(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            HashAlgorithm::Sha512.digest(b"abc"),
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        );
+        assert_eq!(
+            HashAlgorithm::Blake3.digest(b"abc"),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        assert_eq!(
+            HashAlgorithm::Md5.digest(b"abc"),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+    }
+
+    #[test]
+    fn test_lint_component() {
+        let rules = [LintRule::Console, LintRule::Eval, LintRule::InnerHtml];
+
+        assert!(lint_component(
+            "a/widget/A",
+            "return <div />;",
+            &rules,
+            false
+        ));
+        assert!(lint_component(
+            "a/widget/A",
+            "console.log(props); return <div />;",
+            &rules,
+            false
+        ));
+        assert!(!lint_component(
+            "a/widget/A",
+            "console.log(props); return <div />;",
+            &rules,
+            true
+        ));
+        assert!(!lint_component(
+            "a/widget/A",
+            "eval(props.code); return <div />;",
+            &rules,
+            false
+        ));
+        assert!(lint_component(
+            "a/widget/A",
+            "eval(props.code); return <div />;",
+            &[LintRule::Console],
+            false
+        ));
+    }
+
+    #[test]
+    fn test_is_stale() {
+        let hour = Duration::from_secs(3600);
+
+        assert!(is_stale(SystemTime::now() - 2 * hour, hour));
+        assert!(!is_stale(SystemTime::now(), hour));
+        assert!(!is_stale(SystemTime::now() + hour, hour));
+    }
+
+    #[test]
+    fn test_component_stats_csv() {
+        let stats = vec![
+            ComponentStats {
+                key: ComponentKey::parse("test.near/widget/b").unwrap(),
+                source_path: "./src/b.jsx".into(),
+                raw_bytes: 20,
+                post_replacement_bytes: 24,
+                load_duration: Duration::from_micros(150),
+                mtime: Some(UNIX_EPOCH + Duration::from_secs(1700000000)),
+            },
+            ComponentStats {
+                key: ComponentKey::parse("test.near/widget/a").unwrap(),
+                source_path: "./src/a,b.jsx".into(),
+                raw_bytes: 10,
+                post_replacement_bytes: 10,
+                load_duration: Duration::from_micros(42),
+                mtime: None,
+            },
+        ];
+
+        assert_eq!(
+            component_stats_csv(&stats),
+            "key,source_path,raw_bytes,post_replacement_bytes,load_duration_us,mtime\n\
+             test.near/widget/a,\"./src/a,b.jsx\",10,10,42,\n\
+             test.near/widget/b,./src/b.jsx,20,24,150,1700000000\n"
+        );
+    }
+
+    #[test]
+    fn test_size_histogram() {
+        let components = [0, 1023, 1024, 60 * 1024]
+            .iter()
+            .enumerate()
+            .map(|(index, size)| {
+                (
+                    ComponentKey::parse(&format!("test.near/widget/C{index}")).unwrap(),
+                    ComponentCode {
+                        code: "a".repeat(*size),
+                        css: None,
+                        hash: None,
+                        url: None,
+                    },
+                )
+            })
+            .collect::<HashMap<ComponentKey, ComponentCode>>();
+
+        let buckets = size_histogram(&components);
+        assert_eq!(buckets, [2, 1, 0, 1]);
+        assert_eq!(
+            format_size_histogram(&buckets).lines().next().unwrap(),
+            format!("    0-1KB | {} 2", "#".repeat(40))
+        );
+    }
+
+    #[test]
+    fn test_format_component_tree() {
+        let keys = [
+            "alice.near/widget/Nested.Bar",
+            "alice.near/widget/Foo",
+            "bob.near/widget/Nested.Deep.Baz",
+        ]
+        .map(|key| ComponentKey::parse(key).unwrap());
+
+        assert_eq!(
+            format_component_tree(&keys, DEFAULT_ID_SEPARATOR),
+            "alice.near\n  widget\n    Foo\n    Nested\n      Bar\nbob.near\n  widget\n    Nested\n      Deep\n        Baz\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_components_from_tar() {
+        let components = load_components_from_tar(
+            "./test/components.tar.gz".into(),
+            "test.near",
+            Arc::new(ReplacementsMap::default()),
+        )
+        .await
+        .unwrap();
+
+        let mut keys = components
+            .keys()
+            .map(ToString::to_string)
+            .collect::<Vec<String>>();
+        keys.sort();
+
+        assert_eq!(
+            keys,
+            vec![
+                "test.near/widget/Posts",
+                "test.near/widget/Sub.a",
+                "test.near/widget/ts"
+            ]
+        );
+        assert_eq!(
+            components[&ComponentKey::parse("test.near/widget/Sub.a").unwrap()].code,
+            "return <>a</>;\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_components_from_tar_with_options() {
+        let components = load_components_from_tar_with_options(HandleRequestOptions {
+            path: "./test/components.tar.gz".into(),
+            account: "test.near".to_string(),
+            web_engine: true,
+            key_format: WEB_ENGINE_KEY_FORMAT.to_string(),
+            id_separator: "_".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let mut keys = components
+            .keys()
+            .map(ToString::to_string)
+            .collect::<Vec<String>>();
+        keys.sort();
+
+        assert_eq!(
+            keys,
+            vec!["test.near/Posts", "test.near/Sub_a", "test.near/ts"]
+        );
+
+        assert!(load_components_from_tar_with_options(HandleRequestOptions {
+            path: "./test/components.tar.gz".into(),
+            account: "test.near".to_string(),
+            max_file_size: Some(1),
+            ..Default::default()
+        })
+        .await
+        .is_err());
+    }
+
+    #[test]
+    fn test_strip_module_exports() {
+        let code = "import { Button } from \"./Button\";\n\
+                    export const Title = () => <h1>Title</h1>;\n\
+                    export default function Page() {\n  return <Title />;\n}\n\
+                    export { Button, Title as Heading };\n\
+                    export default Page;\n\
+                    return <Page />;\n";
+
+        assert_eq!(
+            strip_module_exports(code),
+            "import { Button } from \"./Button\";\n\
+             const Title = () => <h1>Title</h1>;\n\
+             function Page() {\n  return <Title />;\n}\n\
+             return <Page />;\n"
+        );
+
+        let code = "export default (props) => {\n  return <h1>{props.title}</h1>;\n};\n";
+        assert_eq!(
+            strip_module_exports(code),
+            "(props) => {\n  return <h1>{props.title}</h1>;\n};\n"
+        );
+
+        let code = "export default {\n  title: \"Title\",\n};\nexport default Page\n";
+        assert_eq!(strip_module_exports(code), "{\n  title: \"Title\",\n};\n");
+    }
+
+    #[test]
+    fn test_explain_key() {
+        let explanation = explain_key(
+            Path::new("./src"),
+            Path::new("./src/ui/Foo.Bar.jsx"),
+            "alice.near",
+            DEFAULT_KEY_FORMAT,
+            "/",
+        )
+        .unwrap();
+
+        assert_eq!(
+            explanation,
+            KeyExplanation {
+                source_path: "./src/ui/Foo.Bar.jsx".into(),
+                prefix: "ui/".to_string(),
+                name: "Foo/Bar".to_string(),
+                id_separator: "/".to_string(),
+                key_format: DEFAULT_KEY_FORMAT.to_string(),
+                join_string: Some("/widget/".to_string()),
+                key: "alice.near/widget/ui/Foo/Bar".to_string(),
+            }
+        );
+
+        let explanation = explain_key(
+            Path::new("./src"),
+            Path::new("./src/Foo.tsx"),
+            "alice.near",
+            WEB_ENGINE_KEY_FORMAT,
+            DEFAULT_ID_SEPARATOR,
+        )
+        .unwrap();
+        assert_eq!(explanation.join_string.as_deref(), Some("/"));
+        assert_eq!(explanation.key, "alice.near/Foo");
+        assert!(explanation
+            .to_string()
+            .contains("Key format:  {account}/{prefix}{name}\nJoin string: \"/\"\n"));
+
+        let explanation = explain_key(
+            Path::new("./src"),
+            Path::new("./src/Foo.jsx"),
+            "alice.near",
+            "{account}/{name}.{ext}",
+            DEFAULT_ID_SEPARATOR,
+        )
+        .unwrap();
+        assert_eq!(explanation.join_string, None);
+        assert_eq!(explanation.key, "alice.near/Foo.jsx");
+        assert!(explain_key(
+            Path::new("./src"),
+            Path::new("./other/Foo.jsx"),
+            "alice.near",
+            DEFAULT_KEY_FORMAT,
+            DEFAULT_ID_SEPARATOR,
+        )
+        .is_err());
+
+        // paths spelled without `./` are inside the directory too
+        for (root, file, key) in [
+            (
+                ".",
+                "test/components/Sub/a.jsx",
+                "alice.near/widget/test.components.Sub.a",
+            ),
+            (
+                "./test",
+                "test/components/Sub/a.jsx",
+                "alice.near/widget/components.Sub.a",
+            ),
+            (
+                "test",
+                "./test/components/Sub/a.jsx",
+                "alice.near/widget/components.Sub.a",
+            ),
+        ] {
+            let explanation = explain_key(
+                Path::new(root),
+                Path::new(file),
+                "alice.near",
+                DEFAULT_KEY_FORMAT,
+                DEFAULT_ID_SEPARATOR,
+            )
+            .unwrap();
+            assert_eq!(explanation.source_path, Path::new(file), "{root} {file}");
+            assert_eq!(explanation.key, key, "{root} {file}");
+        }
+        assert_eq!(
+            explain_key(
+                Path::new("src"),
+                Path::new("./src/ui/Foo.jsx"),
+                "alice.near",
+                DEFAULT_KEY_FORMAT,
+                DEFAULT_ID_SEPARATOR,
+            )
+            .unwrap()
+            .key,
+            "alice.near/widget/ui.Foo"
+        );
+    }
+}
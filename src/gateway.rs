@@ -0,0 +1,154 @@
+use crate::{
+    components::{ComponentCode, FileList},
+    key::ComponentKey,
+};
+use anyhow::anyhow;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+
+/// A widget as stored in SocialDB, either its code or an object holding the code under `""`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SocialWidget {
+    Code(String),
+    Entry {
+        #[serde(rename = "")]
+        code: Option<String>,
+    },
+}
+
+#[derive(Deserialize)]
+struct SocialAccount {
+    #[serde(default)]
+    widget: HashMap<String, SocialWidget>,
+}
+
+/// Fetches the production components of an account from a BOS gateway's SocialDB API
+/// (e.g. `https://api.near.social`)
+#[derive(Clone, Debug)]
+pub struct GatewayClient {
+    gateway_url: String,
+    client: reqwest::Client,
+}
+
+impl GatewayClient {
+    pub fn new(gateway_url: &str) -> Self {
+        Self {
+            gateway_url: gateway_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Fetches every widget of `account`, keyed like the loader serves them (`<account>/widget/<name>`)
+    pub async fn fetch_components(&self, account: &str) -> Result<FileList, anyhow::Error> {
+        let url = format!("{}/get", self.gateway_url);
+
+        let response = self
+            .client
+            .post(&url)
+            .json(&json!({ "keys": [format!("{account}/widget/**")] }))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| {
+                anyhow!(
+                    "Failed to fetch components from {} \n Error: {:?}",
+                    url,
+                    err
+                )
+            })?;
+
+        let mut accounts = response
+            .json::<HashMap<String, SocialAccount>>()
+            .await
+            .map_err(|err| {
+                anyhow!(
+                    "Invalid component data from gateway {} \n Error: {:?}",
+                    url,
+                    err
+                )
+            })?;
+
+        let mut components = HashMap::new();
+
+        for (name, widget) in accounts
+            .remove(account)
+            .map(|social_account| social_account.widget)
+            .unwrap_or_default()
+        {
+            let code = match widget {
+                SocialWidget::Code(code) => code,
+                SocialWidget::Entry { code: Some(code) } => code,
+                // deleted widgets keep their metadata without code
+                SocialWidget::Entry { code: None } => continue,
+            };
+
+            let key = ComponentKey::parse(&format!("{account}/widget/{name}")).map_err(|err| {
+                anyhow!(
+                    "Invalid component name {} from gateway {} \n Error: {}",
+                    name,
+                    url,
+                    err
+                )
+            })?;
+
+            components.insert(
+                key,
+                ComponentCode {
+                    code,
+                    css: None,
+                    hash: None,
+                    url: None,
+                },
+            );
+        }
+
+        Ok(FileList {
+            entry_point: None,
+            metadata: None,
+            accounts: None,
+            components,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::Filter;
+
+    #[tokio::test]
+    async fn test_fetch_components() {
+        let social_db = warp::post()
+            .and(warp::path("get"))
+            .and(warp::body::json())
+            .map(|body: serde_json::Value| {
+                assert_eq!(body, json!({ "keys": ["alice.near/widget/**"] }));
+
+                warp::reply::json(&json!({
+                    "alice.near": {
+                        "widget": {
+                            "Feed": "return <>Feed</>;",
+                            "Profile": { "": "return <>Profile</>;", "metadata": {} },
+                            "Deleted": { "metadata": {} }
+                        }
+                    }
+                }))
+            });
+        let (address, server) = warp::serve(social_db).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let file_list = GatewayClient::new(&format!("http://{address}/"))
+            .fetch_components("alice.near")
+            .await
+            .unwrap();
+
+        assert_eq!(file_list.components.len(), 2);
+        assert_eq!(
+            file_list.components["alice.near/widget/Profile"].code,
+            "return <>Profile</>;"
+        );
+        assert!(file_list.components.contains_key("alice.near/widget/Feed"));
+    }
+}
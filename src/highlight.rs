@@ -0,0 +1,97 @@
+use std::{str::FromStr, sync::LazyLock};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Color, ScopeSelectors, StyleModifier, Theme, ThemeItem, ThemeSettings},
+    parsing::SyntaxSet,
+    util::{as_24_bit_terminal_escaped, LinesWithEndings},
+};
+
+static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+
+/// Monokai colors by scope, built in code since syntect doesn't ship the theme
+const MONOKAI_SCOPES: &[(&str, u32)] = &[
+    ("comment", 0x75715e),
+    ("string", 0xe6db74),
+    (
+        "constant.numeric, constant.language, constant.character",
+        0xae81ff,
+    ),
+    ("keyword, storage, entity.name.tag", 0xf92672),
+    (
+        "entity.name.function, entity.name.class, entity.other.attribute-name",
+        0xa6e22e,
+    ),
+    ("support, storage.type.function.arrow", 0x66d9ef),
+    ("variable.parameter", 0xfd971f),
+];
+
+static MONOKAI: LazyLock<Theme> = LazyLock::new(|| Theme {
+    name: Some("Monokai".to_string()),
+    settings: ThemeSettings {
+        foreground: Some(color(0xf8f8f2)),
+        background: Some(color(0x272822)),
+        ..Default::default()
+    },
+    scopes: MONOKAI_SCOPES
+        .iter()
+        .map(|(scope, rgb)| ThemeItem {
+            scope: ScopeSelectors::from_str(scope).unwrap(),
+            style: StyleModifier {
+                foreground: Some(color(*rgb)),
+                ..Default::default()
+            },
+        })
+        .collect(),
+    ..Default::default()
+});
+
+fn color(rgb: u32) -> Color {
+    Color {
+        r: (rgb >> 16) as u8,
+        g: (rgb >> 8) as u8,
+        b: rgb as u8,
+        a: 0xff,
+    }
+}
+
+/// Highlights component code with 24-bit ANSI colors on the terminal's own background. JSX and
+/// TypeScript are highlighted as JavaScript, the closest syntax syntect ships with
+pub fn highlight_code(code: &str) -> String {
+    let syntax = SYNTAX_SET
+        .find_syntax_by_extension("js")
+        .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
+    let mut highlighter = HighlightLines::new(syntax, &MONOKAI);
+
+    let mut highlighted = String::new();
+    for line in LinesWithEndings::from(code) {
+        match highlighter.highlight_line(line, &SYNTAX_SET) {
+            Ok(ranges) => highlighted.push_str(&as_24_bit_terminal_escaped(&ranges, false)),
+            // leave the rest uncolored rather than dropping code
+            Err(_) => highlighted.push_str(line),
+        }
+    }
+
+    // reset so the colors don't leak into the following output
+    highlighted.push_str("\x1b[0m");
+
+    highlighted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    #[test]
+    fn test_highlight_code() {
+        let code = "// greeting\nconst name = \"Alice\";\nreturn <>{name}</>;\n";
+        let highlighted = highlight_code(code);
+
+        // keywords in Monokai pink, strings in yellow
+        assert!(highlighted.contains("\x1b[38;2;249;38;114mconst"));
+        assert!(highlighted.contains("\x1b[38;2;230;219;116m"));
+
+        let escapes = Regex::new("\x1b\\[[0-9;]*m").unwrap();
+        assert_eq!(escapes.replace_all(&highlighted, ""), code);
+    }
+}
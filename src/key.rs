@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+use std::{borrow::Borrow, fmt, ops::Deref, str::FromStr};
+
+/// A validated component key such as `alice.near/widget/Foo`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct ComponentKey(String);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyParseError {
+    /// The key has no `/` between the account and the component name
+    MissingSeparator(String),
+    /// One of the `/` delimited segments of the key is empty
+    EmptySegment(String),
+    /// The key contains whitespace
+    Whitespace(String),
+}
+
+impl fmt::Display for KeyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyParseError::MissingSeparator(key) => write!(
+                f,
+                "Component key {:?} must have the format <account>/<join>/<name>",
+                key
+            ),
+            KeyParseError::EmptySegment(key) => {
+                write!(f, "Component key {:?} contains an empty segment", key)
+            }
+            KeyParseError::Whitespace(key) => {
+                write!(f, "Component key {:?} contains whitespace", key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for KeyParseError {}
+
+impl ComponentKey {
+    /// Parses keys made of an account and a name separated by `/`, with any join segments in between
+    pub fn parse(s: &str) -> Result<ComponentKey, KeyParseError> {
+        if s.chars().any(char::is_whitespace) {
+            return Err(KeyParseError::Whitespace(s.to_string()));
+        }
+
+        if !s.contains('/') {
+            return Err(KeyParseError::MissingSeparator(s.to_string()));
+        }
+
+        if s.split('/').any(str::is_empty) {
+            return Err(KeyParseError::EmptySegment(s.to_string()));
+        }
+
+        Ok(ComponentKey(s.to_string()))
+    }
+
+    /// The account segment, e.g. `alice.near` for `alice.near/widget/Foo`
+    pub fn account(&self) -> &str {
+        self.0.split('/').next().unwrap_or_default()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for ComponentKey {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for ComponentKey {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for ComponentKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for ComponentKey {
+    type Err = KeyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ComponentKey::parse(s)
+    }
+}
+
+impl TryFrom<String> for ComponentKey {
+    type Error = KeyParseError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        ComponentKey::parse(&value)
+    }
+}
+
+impl From<ComponentKey> for String {
+    fn from(key: ComponentKey) -> String {
+        key.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_valid_keys() {
+        let key = ComponentKey::parse("alice.near/widget/Foo.Bar").unwrap();
+
+        assert_eq!(&*key, "alice.near/widget/Foo.Bar");
+        assert_eq!(key.account(), "alice.near");
+        assert!(ComponentKey::parse("alice.near/Foo").is_ok());
+    }
+
+    #[test]
+    fn test_parse_invalid_keys() {
+        assert_eq!(
+            ComponentKey::parse("Foo"),
+            Err(KeyParseError::MissingSeparator("Foo".to_string()))
+        );
+        assert_eq!(
+            ComponentKey::parse("alice.near/widget/"),
+            Err(KeyParseError::EmptySegment(
+                "alice.near/widget/".to_string()
+            ))
+        );
+        assert_eq!(
+            ComponentKey::parse("/widget/Foo"),
+            Err(KeyParseError::EmptySegment("/widget/Foo".to_string()))
+        );
+        assert_eq!(
+            ComponentKey::parse("alice.near/widget/My Foo"),
+            Err(KeyParseError::Whitespace(
+                "alice.near/widget/My Foo".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_serde_roundtrip() {
+        let key = ComponentKey::parse("alice.near/widget/Foo").unwrap();
+        let json = serde_json::to_string(&key).unwrap();
+
+        assert_eq!(json, "\"alice.near/widget/Foo\"");
+        assert_eq!(serde_json::from_str::<ComponentKey>(&json).unwrap(), key);
+        assert!(serde_json::from_str::<ComponentKey>("\"Foo\"").is_err());
+    }
+
+    /// alice.near/widget/Typed
+    #[derive(bos_loader_macros::ComponentKey)]
+    struct Typed;
+
+    #[test]
+    fn test_derive_component_key() {
+        assert_eq!(Typed::component_key(), "alice.near/widget/Typed");
+        assert_eq!(Typed.to_string(), "alice.near/widget/Typed");
+        assert!(ComponentKey::parse(Typed::component_key()).is_ok());
+    }
+}
@@ -0,0 +1,16 @@
+pub mod access_log;
+pub mod accounts;
+pub mod budget;
+pub mod cache;
+pub mod cli;
+pub mod components;
+pub mod gateway;
+pub mod highlight;
+pub mod key;
+pub mod metrics;
+pub mod path;
+pub mod replacements;
+pub mod rpc;
+pub mod server;
+pub mod snapshot;
+pub mod watch;
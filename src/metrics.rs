@@ -0,0 +1,133 @@
+use crate::access_log::rejection_status;
+use prometheus::{Encoder, Histogram, HistogramOpts, IntCounter, Registry, TextEncoder};
+use std::{convert::Infallible, time::Instant};
+use warp::{reply::Response, Filter, Rejection, Reply};
+
+/// Request telemetry served on `/metrics` in the Prometheus text exposition format
+#[derive(Clone, Debug)]
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounter,
+    errors: IntCounter,
+    latency: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        // a registry of its own rather than the global one, so every server counts separately
+        let registry = Registry::new();
+
+        let requests = IntCounter::new("bos_loader_requests_total", "Requests handled").unwrap();
+        let errors = IntCounter::new(
+            "bos_loader_errors_total",
+            "Requests answered with a 4xx or 5xx status",
+        )
+        .unwrap();
+        let latency = Histogram::with_opts(HistogramOpts::new(
+            "bos_loader_response_latency_seconds",
+            "Time from receiving a request to having its response",
+        ))
+        .unwrap();
+
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry.register(Box::new(errors.clone())).unwrap();
+        registry.register(Box::new(latency.clone())).unwrap();
+
+        Self {
+            registry,
+            requests,
+            errors,
+            latency,
+        }
+    }
+
+    /// Renders every metric in the text exposition format
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        // writing to a Vec only fails for invalid metrics, which are registered above
+        let _ = TextEncoder::new().encode(&self.registry.gather(), &mut buffer);
+
+        String::from_utf8_lossy(&buffer).into_owned()
+    }
+}
+
+/// Counts every request handled by `filter` and its latency in `metrics`, leaving responses and
+/// rejections untouched
+pub(crate) fn with_metrics<F, R>(
+    filter: F,
+    metrics: Option<Metrics>,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    warp::any()
+        .map(Instant::now)
+        .and(
+            filter
+                .map(|reply: R| Ok::<_, Rejection>(reply.into_response()))
+                .or_else(|rejection| async move { Ok::<_, Infallible>((Err(rejection),)) }),
+        )
+        .and_then(
+            move |started: Instant, result: Result<Response, Rejection>| {
+                let metrics = metrics.clone();
+
+                async move {
+                    let Some(metrics) = metrics else {
+                        return result;
+                    };
+
+                    let status = match &result {
+                        Ok(response) => response.status(),
+                        Err(rejection) => rejection_status(rejection),
+                    };
+
+                    metrics.requests.inc();
+                    if status.is_client_error() || status.is_server_error() {
+                        metrics.errors.inc();
+                    }
+                    metrics.latency.observe(started.elapsed().as_secs_f64());
+
+                    result
+                }
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::http::StatusCode;
+
+    #[tokio::test]
+    async fn test_with_metrics() {
+        let metrics = Metrics::new();
+        let filter = with_metrics(
+            warp::path("ok").map(|| "ok").or(warp::path("fail")
+                .map(|| warp::reply::with_status("fail", StatusCode::INTERNAL_SERVER_ERROR))),
+            Some(metrics.clone()),
+        );
+
+        for path in ["/ok", "/ok", "/fail", "/missing"] {
+            let _ = warp::test::request().path(path).reply(&filter).await;
+        }
+
+        let encoded = metrics.encode();
+        assert!(
+            encoded.contains("bos_loader_requests_total 4\n"),
+            "{encoded}"
+        );
+        assert!(encoded.contains("bos_loader_errors_total 2\n"), "{encoded}");
+        assert!(
+            encoded.contains("bos_loader_response_latency_seconds_count 4\n"),
+            "{encoded}"
+        );
+        assert!(encoded.contains("# TYPE bos_loader_response_latency_seconds histogram"));
+    }
+}
@@ -0,0 +1,387 @@
+use crate::{
+    components::{
+        component_file_name, format_component_key, COMPONENT_EXTENSIONS, DEFAULT_KEY_FORMAT,
+        WEB_ENGINE_KEY_FORMAT,
+    },
+    key::ComponentKey,
+};
+use regex::Regex;
+use std::{
+    collections::HashSet,
+    path::{Component, Path, PathBuf},
+};
+
+/// The placeholders of a key format, see [`format_component_key`]
+const KEY_FORMAT_PLACEHOLDERS: [&str; 4] = ["account", "prefix", "name", "ext"];
+
+/// The parts the key of a component file is assembled from, see
+/// [`ComponentPathResolver::key_parts`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyParts {
+    /// The key prefix followed by every directory between `base_path` and the file, each joined
+    /// with `id_separator`
+    pub prefix: String,
+    /// The dot separated parts of the file name before its extension, joined with `id_separator`
+    pub name: String,
+    pub extension: String,
+    /// `key_format` filled in with the parts, which isn't validated as a [`ComponentKey`]
+    pub key: String,
+}
+
+/// Maps component files under `base_path` to the keys they are served as and back. Keys follow
+/// `key_format`, the default one of the mode (`<account>/widget/<prefix><name>`, or
+/// `<account>/<prefix><name>` in BOS Web Engine mode) unless set with `--component-key-format`.
+/// Directories and dotted file names are joined into the name with `id_separator`. This is how
+/// `load_components`, `explain_key` and the archive and test mode loaders derive keys
+#[derive(Clone, Debug, PartialEq)]
+pub struct ComponentPathResolver {
+    pub base_path: PathBuf,
+    pub account: String,
+    /// Prepended to the `{prefix}` of every key
+    pub prefix: String,
+    pub web_engine: bool,
+    pub key_format: String,
+    pub id_separator: String,
+}
+
+impl ComponentPathResolver {
+    /// A resolver with the default key format of the mode and no key prefix
+    pub fn new(
+        base_path: impl Into<PathBuf>,
+        account: &str,
+        web_engine: bool,
+        id_separator: &str,
+    ) -> Self {
+        let key_format = if web_engine {
+            WEB_ENGINE_KEY_FORMAT
+        } else {
+            DEFAULT_KEY_FORMAT
+        };
+
+        Self {
+            base_path: base_path.into(),
+            account: account.to_string(),
+            prefix: String::new(),
+            web_engine,
+            key_format: key_format.to_string(),
+            id_separator: id_separator.to_string(),
+        }
+    }
+
+    /// What joins the account and the prefix in `key_format`, e.g. `/widget/` by default and `/`
+    /// in BOS Web Engine mode. `None` when the format doesn't start with `{account}` followed by
+    /// `{prefix}`
+    pub fn join_string(&self) -> Option<&str> {
+        let (join_string, _) = self
+            .key_format
+            .strip_prefix("{account}")?
+            .split_once("{prefix}")?;
+
+        (!join_string.contains('{')).then_some(join_string)
+    }
+
+    /// The parts of the key of the component file at `file_path`, `None` when it isn't inside
+    /// `base_path` or isn't a component file
+    pub fn key_parts(&self, file_path: &Path) -> Option<KeyParts> {
+        let relative_path = file_path.strip_prefix(&self.base_path).ok()?;
+        let file_name = relative_path.file_name()?.to_string_lossy();
+        let (name, extension) = component_file_name(&file_name, &self.id_separator)?;
+
+        let mut prefix = self.prefix.clone();
+        for component in relative_path
+            .parent()
+            .into_iter()
+            .flat_map(Path::components)
+        {
+            match component {
+                Component::Normal(directory) => {
+                    prefix.push_str(&directory.to_string_lossy());
+                    prefix.push_str(&self.id_separator);
+                }
+                Component::CurDir => {}
+                // `..` and roots would point outside of `base_path`
+                _ => return None,
+            }
+        }
+
+        let key = format_component_key(&self.key_format, &self.account, &prefix, &name, extension);
+
+        Some(KeyParts {
+            prefix,
+            name,
+            extension: extension.to_string(),
+            key,
+        })
+    }
+
+    /// The key of the component file at `file_path`, `None` when it isn't inside `base_path`,
+    /// isn't a component file or gives an invalid key
+    pub fn resolve(&self, file_path: &Path) -> Option<ComponentKey> {
+        ComponentKey::parse(&self.key_parts(file_path)?.key).ok()
+    }
+
+    /// Matches the keys `key_format` gives for `account`, capturing the `prefix`, `name` and `ext`
+    /// filled in. A placeholder used more than once is only captured the first time
+    fn key_pattern(&self) -> Regex {
+        let mut pattern = String::from("^");
+        let mut captured = HashSet::new();
+        let mut rest = self.key_format.as_str();
+
+        while let Some(start) = rest.find('{') {
+            pattern.push_str(&regex::escape(&rest[..start]));
+            rest = &rest[start..];
+
+            let Some(placeholder) = KEY_FORMAT_PLACEHOLDERS
+                .into_iter()
+                .find(|placeholder| rest[1..].starts_with(&format!("{placeholder}}}")))
+            else {
+                pattern.push_str(r"\{");
+                rest = &rest[1..];
+                continue;
+            };
+
+            let value = match placeholder {
+                "account" => regex::escape(&self.account),
+                "ext" => COMPONENT_EXTENSIONS.join("|"),
+                // the prefix ends where the name starts, splitting them doesn't matter
+                "prefix" => ".*?".to_string(),
+                _ => ".*".to_string(),
+            };
+
+            if captured.insert(placeholder) {
+                pattern.push_str(&format!("(?P<{placeholder}>{value})"));
+            } else {
+                pattern.push_str(&format!("(?:{value})"));
+            }
+            rest = &rest[placeholder.len() + 2..];
+        }
+
+        pattern.push_str(&regex::escape(rest));
+        pattern.push('$');
+
+        // every literal part is escaped
+        Regex::new(&pattern).unwrap()
+    }
+
+    /// The file `key` is expected to be loaded from, reading the key back through `key_format`
+    /// and making every `id_separator` separated part of its prefix and name but the last a
+    /// directory. Names with `id_separator` can also come from dotted file names, which this
+    /// doesn't guess. The extension is the `{ext}` of the key, or `.tsx` in BOS Web Engine mode
+    /// and `.jsx` otherwise. `None` when the key doesn't follow `key_format` for `account`
+    pub fn resolve_reverse(&self, key: &ComponentKey) -> Option<PathBuf> {
+        let captures = self.key_pattern().captures(key.as_str())?;
+        let part = |placeholder| captures.name(placeholder).map_or("", |part| part.as_str());
+
+        let name = format!("{}{}", part("prefix"), part("name"));
+        let name = name.strip_prefix(self.prefix.as_str()).unwrap_or(&name);
+        if name.is_empty() {
+            return None;
+        }
+
+        let extension = match part("ext") {
+            "" if self.web_engine => "tsx",
+            "" => "jsx",
+            extension => extension,
+        };
+
+        let mut parts = if self.id_separator.is_empty() {
+            vec![name]
+        } else {
+            name.split(self.id_separator.as_str())
+                .collect::<Vec<&str>>()
+        };
+        let file_stem = parts.pop().unwrap_or_default();
+
+        let mut path = self.base_path.clone();
+        path.extend(parts);
+        path.push(format!("{file_stem}.{extension}"));
+
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: &str) -> ComponentKey {
+        ComponentKey::parse(key).unwrap()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let resolver = ComponentPathResolver::new("./src", "alice.near", false, ".");
+
+        for (file_path, expected) in [
+            ("./src/Foo.jsx", Some("alice.near/widget/Foo")),
+            ("./src/Foo.tsx", Some("alice.near/widget/Foo")),
+            ("./src/plain.js", Some("alice.near/widget/plain")),
+            ("./src/Nested/Bar.ts", Some("alice.near/widget/Nested.Bar")),
+            ("./src/a/b/C.jsx", Some("alice.near/widget/a.b.C")),
+            (
+                "./src/Dotted.Name.jsx",
+                Some("alice.near/widget/Dotted.Name"),
+            ),
+            ("./src/./Foo.jsx", Some("alice.near/widget/Foo")),
+            ("./src/notes.md", None),
+            ("./src/Foo", None),
+            ("./src/With Space.jsx", None),
+            ("./other/Foo.jsx", None),
+            ("./src/../Foo.jsx", None),
+        ] {
+            assert_eq!(
+                resolver.resolve(Path::new(file_path)),
+                expected.map(key),
+                "{file_path}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_resolve_web_engine_and_id_separator() {
+        let resolver = ComponentPathResolver::new("/app", "test.near", true, "_");
+
+        assert_eq!(
+            resolver.resolve(Path::new("/app/ui/Button.tsx")),
+            Some(key("test.near/ui_Button"))
+        );
+        assert_eq!(
+            resolver.resolve(Path::new("/app/Dotted.Name.tsx")),
+            Some(key("test.near/Dotted_Name"))
+        );
+
+        let resolver = ComponentPathResolver::new("/app", "test.near", false, "");
+
+        assert_eq!(
+            resolver.resolve(Path::new("/app/ui/Button.jsx")),
+            Some(key("test.near/widget/uiButton"))
+        );
+    }
+
+    #[test]
+    fn test_resolve_key_format_and_prefix() {
+        let resolver = ComponentPathResolver {
+            prefix: "Vendor.".to_string(),
+            key_format: "{account}/component/{prefix}{name}".to_string(),
+            ..ComponentPathResolver::new("./src", "alice.near", false, ".")
+        };
+
+        assert_eq!(
+            resolver.key_parts(Path::new("./src/ui/Button.tsx")),
+            Some(KeyParts {
+                prefix: "Vendor.ui.".to_string(),
+                name: "Button".to_string(),
+                extension: "tsx".to_string(),
+                key: "alice.near/component/Vendor.ui.Button".to_string(),
+            })
+        );
+        assert_eq!(resolver.join_string(), Some("/component/"));
+        assert_eq!(
+            resolver.resolve_reverse(&key("alice.near/component/Vendor.ui.Button")),
+            Some(PathBuf::from("./src/ui/Button.jsx"))
+        );
+        assert_eq!(
+            resolver.resolve_reverse(&key("alice.near/widget/Vendor.ui.Button")),
+            None
+        );
+
+        let resolver = ComponentPathResolver {
+            key_format: "{account}/{name}.{ext}".to_string(),
+            ..ComponentPathResolver::new("./src", "alice.near", false, ".")
+        };
+
+        assert_eq!(
+            resolver.resolve(Path::new("./src/Foo.jsx")),
+            Some(key("alice.near/Foo.jsx"))
+        );
+        assert_eq!(resolver.join_string(), None);
+        assert_eq!(
+            ComponentPathResolver::new("./src", "alice.near", false, ".").join_string(),
+            Some("/widget/")
+        );
+        assert_eq!(
+            ComponentPathResolver::new("./src", "alice.near", true, ".").join_string(),
+            Some("/")
+        );
+    }
+
+    #[test]
+    fn test_resolve_reverse() {
+        let resolver = ComponentPathResolver::new("./src", "alice.near", false, ".");
+
+        for (key_str, expected) in [
+            ("alice.near/widget/Foo", "./src/Foo.jsx"),
+            ("alice.near/widget/Nested.Bar", "./src/Nested/Bar.jsx"),
+            ("alice.near/widget/a.b.C", "./src/a/b/C.jsx"),
+        ] {
+            assert_eq!(
+                resolver.resolve_reverse(&key(key_str)),
+                Some(PathBuf::from(expected)),
+                "{key_str}"
+            );
+        }
+
+        let resolver = ComponentPathResolver::new("/app", "test.near", true, "_");
+        assert_eq!(
+            resolver.resolve_reverse(&key("test.near/ui_Button")),
+            Some(PathBuf::from("/app/ui/Button.tsx"))
+        );
+
+        let resolver = ComponentPathResolver::new("/app", "test.near", false, "");
+        assert_eq!(
+            resolver.resolve_reverse(&key("test.near/widget/uiButton")),
+            Some(PathBuf::from("/app/uiButton.jsx"))
+        );
+
+        let resolver = ComponentPathResolver::new("./src", "alice.near", false, ".");
+        assert_eq!(resolver.resolve_reverse(&key("bob.near/widget/Foo")), None);
+    }
+
+    #[test]
+    fn test_resolve_round_trip() {
+        let key_formats = [
+            None,
+            Some("{account}/component/{prefix}{name}"),
+            Some("{account}/{prefix}{name}.{ext}"),
+            Some("{account}/widget/{name}/v1"),
+        ];
+
+        for key_format in key_formats {
+            for (web_engine, id_separator) in [
+                (false, "."),
+                (true, "."),
+                (false, "_"),
+                (true, "__"),
+                (false, "/"),
+            ] {
+                let resolver = ComponentPathResolver {
+                    prefix: "Vendor".to_string() + id_separator,
+                    ..ComponentPathResolver::new("./src", "alice.near", web_engine, id_separator)
+                };
+                let resolver = match key_format {
+                    Some(key_format) => ComponentPathResolver {
+                        key_format: key_format.to_string(),
+                        ..resolver
+                    },
+                    None => resolver,
+                };
+
+                for name in ["Foo", "Nested/Bar", "a/b/C"] {
+                    let extension = if web_engine { "tsx" } else { "jsx" };
+                    let file_path = PathBuf::from(format!("./src/{name}.{extension}"));
+                    let key = resolver.resolve(&file_path).unwrap();
+
+                    // without `{prefix}` the directories aren't part of the key
+                    let expected = if key_format.is_some_and(|format| !format.contains("{prefix}"))
+                    {
+                        Path::new("./src").join(file_path.file_name().unwrap())
+                    } else {
+                        file_path
+                    };
+
+                    assert_eq!(resolver.resolve_reverse(&key), Some(expected), "{key}");
+                }
+            }
+        }
+    }
+}
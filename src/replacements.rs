@@ -0,0 +1,1447 @@
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use anyhow::anyhow;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use config::{Config, ConfigError};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+};
+use tokio::fs;
+
+/// A placeholder replaced in a component's code
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Substitution {
+    pub placeholder: String,
+    pub replaced_with: String,
+    pub occurrences: usize,
+}
+
+/// Placeholders resolved by the loader itself, which a replacements map can't define
+pub const RESERVED_KEYS: &[&str] = &["REPL_ACCOUNT"];
+
+/// How `ReplacementsMap::merge` resolves keys present in both maps
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MergeStrategy {
+    /// Values from the merged map win
+    Override,
+    /// Values already in the map win
+    KeepExisting,
+}
+
+/// Replacement values keyed by placeholder name, e.g. `REPL_API_URL` for `${REPL_API_URL}`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReplacementsMap(HashMap<String, String>);
+
+impl ReplacementsMap {
+    /// Adds a replacement, rejecting reserved keys and keys that can't be written as `${KEY}`
+    pub fn insert(&mut self, key: String, value: String) -> Result<(), anyhow::Error> {
+        if RESERVED_KEYS.contains(&key.as_str()) {
+            return Err(anyhow!(
+                "Replacement key {} is reserved and can't be set",
+                key
+            ));
+        }
+
+        if !is_placeholder_key(&key) {
+            return Err(anyhow!(
+                "Replacement key {:?} must be non-empty and can't contain whitespace, '$', '{{' or '}}'",
+                key
+            ));
+        }
+
+        self.0.insert(key, value);
+
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.0.get(key)
+    }
+
+    /// Returns the reserved keys present in the map, sorted
+    pub fn contains_reserved(&self) -> Vec<String> {
+        let mut reserved = self
+            .0
+            .keys()
+            .filter(|key| RESERVED_KEYS.contains(&key.as_str()))
+            .cloned()
+            .collect::<Vec<String>>();
+        reserved.sort();
+
+        reserved
+    }
+
+    pub fn merge(&mut self, other: ReplacementsMap, strategy: MergeStrategy) {
+        for (key, value) in other.0 {
+            match strategy {
+                MergeStrategy::Override => {
+                    self.0.insert(key, value);
+                }
+                MergeStrategy::KeepExisting => {
+                    self.0.entry(key).or_insert(value);
+                }
+            }
+        }
+    }
+
+    /// Expands `${KEY}` references to other keys of the map within the values, e.g.
+    /// `${REPL_BASE}/posts`, following chains of references. References to keys outside of the
+    /// map, like `${REPL_ACCOUNT}`, are kept for [`replace_placeholders`], and cycles are an error
+    pub fn resolve_references(&self) -> Result<ReplacementsMap, anyhow::Error> {
+        let mut keys = self.0.keys().collect::<Vec<&String>>();
+        // sorted so the same cycle is always reported from the same key
+        keys.sort();
+
+        let mut resolved = HashMap::new();
+        for key in keys {
+            self.resolve_value(key, &mut resolved, &mut vec![])?;
+        }
+
+        Ok(ReplacementsMap(resolved))
+    }
+
+    /// Resolves the value of `key` depth first, `stack` holds the keys being resolved
+    fn resolve_value(
+        &self,
+        key: &str,
+        resolved: &mut HashMap<String, String>,
+        stack: &mut Vec<String>,
+    ) -> Result<String, anyhow::Error> {
+        if let Some(value) = resolved.get(key) {
+            return Ok(value.clone());
+        }
+
+        if let Some(start) = stack.iter().position(|stacked| stacked == key) {
+            return Err(anyhow!(
+                "Replacement values reference each other in a cycle: {} -> {}",
+                stack[start..].join(" -> "),
+                key
+            ));
+        }
+
+        stack.push(key.to_string());
+
+        let value = &self.0[key];
+        let mut expanded = String::new();
+        let mut end = 0;
+
+        for captures in PLACEHOLDER.captures_iter(value) {
+            let (placeholder, reference) = (captures.get(0).unwrap(), &captures[1]);
+
+            if self.0.contains_key(reference) {
+                expanded.push_str(&value[end..placeholder.start()]);
+                expanded.push_str(&self.resolve_value(reference, resolved, stack)?);
+                end = placeholder.end();
+            }
+        }
+        expanded.push_str(&value[end..]);
+
+        stack.pop();
+        resolved.insert(key.to_string(), expanded.clone());
+
+        Ok(expanded)
+    }
+
+    pub fn iter(&self) -> std::collections::hash_map::Iter<'_, String, String> {
+        self.0.iter()
+    }
+
+    pub fn keys(&self) -> std::collections::hash_map::Keys<'_, String, String> {
+        self.0.keys()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a ReplacementsMap {
+    type Item = (&'a String, &'a String);
+    type IntoIter = std::collections::hash_map::Iter<'a, String, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Prefix of the environment variables imported by `replacements_from_env`
+pub const ENV_REPLACEMENT_PREFIX: &str = "REPL_";
+
+/// Environment variable holding the key of `--replacements-encrypt-values`, never imported
+pub const DECRYPT_KEY_ENV: &str = "REPL_DECRYPT_KEY";
+
+/// Imports every `REPL_*` variable as a replacement of the same name, e.g. `REPL_API_URL` for
+/// `${REPL_API_URL}`. Reserved and invalid names are skipped with a warning.
+pub fn replacements_from_env(vars: impl IntoIterator<Item = (String, String)>) -> ReplacementsMap {
+    let mut map = ReplacementsMap::default();
+
+    for (name, value) in vars {
+        if !name.starts_with(ENV_REPLACEMENT_PREFIX) || name == DECRYPT_KEY_ENV {
+            continue;
+        }
+
+        if let Err(err) = map.insert(name.clone(), value) {
+            eprintln!("Warning: skipping environment variable {}: {}", name, err);
+        }
+    }
+
+    map
+}
+
+/// Imports every variable starting with `prefix` as a replacement keyed by the rest of its name,
+/// e.g. `APP_API_URL` for `${API_URL}` with prefix `APP_`. Reserved and invalid names are skipped
+/// with a warning.
+pub fn replacements_from_env_prefix(
+    vars: impl IntoIterator<Item = (String, String)>,
+    prefix: &str,
+) -> Result<ReplacementsMap, anyhow::Error> {
+    if prefix.is_empty() {
+        return Err(anyhow!("The environment variable prefix can't be empty"));
+    }
+
+    let mut map = ReplacementsMap::default();
+
+    for (name, value) in vars {
+        let Some(key) = name.strip_prefix(prefix) else {
+            continue;
+        };
+
+        if name == DECRYPT_KEY_ENV {
+            continue;
+        }
+
+        if let Err(err) = map.insert(key.to_string(), value) {
+            eprintln!("Warning: skipping environment variable {}: {}", name, err);
+        }
+    }
+
+    Ok(map)
+}
+
+/// Parses `KEY=VALUE` pairs from `--replacements-kv`, splitting on the first `=` so values may
+/// contain it. Later pairs win over earlier ones with the same key
+pub fn replacements_from_kv(pairs: &[String]) -> Result<ReplacementsMap, anyhow::Error> {
+    let mut map = ReplacementsMap::default();
+
+    for pair in pairs {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or(anyhow!("Expected KEY=VALUE, got {:?}", pair))?;
+
+        map.insert(key.to_string(), value.to_string())?;
+    }
+
+    Ok(map)
+}
+
+/// What [`replace_placeholders`] did to a piece of code
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReplacementStats {
+    /// Keys with at least one placeholder in the code, sorted
+    pub keys_used: Vec<String>,
+    /// Keys of the replacements map without a placeholder in the code, sorted
+    pub keys_unused: Vec<String>,
+    /// Placeholders replaced across all keys
+    pub total_substitutions: usize,
+    /// One entry per used key, as written to the replacements audit log
+    pub substitutions: Vec<Substitution>,
+}
+
+pub fn replace_placeholders(
+    code: &str,
+    account: &str,
+    replacements_map: &ReplacementsMap,
+    inject_repl_account: bool,
+) -> (String, ReplacementStats) {
+    let mut modified_string = code.to_string();
+    let mut stats = ReplacementStats::default();
+    let repl_account = inject_repl_account.then_some(("REPL_ACCOUNT", account));
+
+    for (key, value) in replacements_map
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .chain(repl_account)
+    {
+        let substring = format!("{}{}{}", "${", key, "}");
+        let occurrences = modified_string.matches(substring.as_str()).count();
+
+        if occurrences > 0 {
+            modified_string = modified_string.replace(&substring, value);
+            stats.keys_used.push(key.to_owned());
+            stats.total_substitutions += occurrences;
+            stats.substitutions.push(Substitution {
+                placeholder: substring,
+                replaced_with: value.to_owned(),
+                occurrences,
+            });
+        } else if replacements_map.get(key).is_some() {
+            stats.keys_unused.push(key.to_owned());
+        }
+    }
+
+    stats.keys_used.sort();
+    stats.keys_unused.sort();
+
+    (modified_string, stats)
+}
+
+/// Returns the keys of `replacements_map` that none of `substitutions` replaced, sorted
+pub(crate) fn unused_replacement_keys<'a>(
+    replacements_map: &ReplacementsMap,
+    substitutions: impl IntoIterator<Item = &'a Substitution>,
+) -> Vec<String> {
+    let used = substitutions
+        .into_iter()
+        .map(|substitution| substitution.placeholder.as_str())
+        .collect::<HashSet<&str>>();
+
+    let mut unused = replacements_map
+        .keys()
+        .filter(|key| !used.contains(format!("{}{}{}", "${", key, "}").as_str()))
+        .cloned()
+        .collect::<Vec<String>>();
+    unused.sort();
+
+    unused
+}
+
+/// Checks the values that look like URLs, starting with `http` or containing `://`, are well-formed
+/// `http(s)://` URLs. Returns a message for each malformed one, sorted by key
+pub fn invalid_url_values(replacements_map: &ReplacementsMap) -> Vec<String> {
+    let mut invalid = replacements_map
+        .iter()
+        .filter(|(_, value)| {
+            value.to_ascii_lowercase().starts_with("http") || value.contains("://")
+        })
+        .filter_map(|(key, value)| {
+            let error = match url::Url::parse(value) {
+                Err(err) => err.to_string(),
+                Ok(url) if !matches!(url.scheme(), "http" | "https") => {
+                    format!("unsupported scheme {:?}", url.scheme())
+                }
+                // the parser fixes up `https:/host`, so check the slashes as written
+                Ok(url) if !value[url.scheme().len()..].starts_with("://") => {
+                    "expected :// after the scheme".to_string()
+                }
+                Ok(url) if url.host_str().is_none_or(str::is_empty) => "missing host".to_string(),
+                Ok(_) => return None,
+            };
+
+            Some((
+                key,
+                format!("{} has a malformed URL {:?}: {}", key, value, error),
+            ))
+        })
+        .collect::<Vec<(&String, String)>>();
+    invalid.sort();
+
+    invalid.into_iter().map(|(_, message)| message).collect()
+}
+
+/// Checks that a replacement key can be written as a `${KEY}` placeholder
+fn is_placeholder_key(key: &str) -> bool {
+    !key.is_empty()
+        && !key
+            .chars()
+            .any(|char| char.is_whitespace() || matches!(char, '$' | '{' | '}'))
+}
+
+/// Checks a replacement key against `^[A-Z][A-Z0-9_]*$`
+pub(crate) fn is_strict_placeholder_key(key: &str) -> bool {
+    let mut chars = key.chars();
+
+    chars.next().is_some_and(|first| first.is_ascii_uppercase())
+        && chars.all(|char| char.is_ascii_uppercase() || char.is_ascii_digit() || char == '_')
+}
+
+/// Removes the lines starting with `//`, leading whitespace aside, so commented JSON files parse
+pub(crate) fn strip_comment_lines(contents: &str) -> String {
+    contents
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+pub async fn read_replacements(
+    path: PathBuf,
+    strict_format: bool,
+    coerce_types: bool,
+    strip_comments: bool,
+) -> Result<Arc<ReplacementsMap>, anyhow::Error> {
+    let mut contents = fs::read_to_string(&path)
+        .await
+        .map_err(|err| anyhow!("Failed to read path {:?} \n Error: {:?}", path, err))?;
+
+    if strip_comments {
+        contents = strip_comment_lines(&contents);
+    }
+
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    let map = match extension {
+        Some("toml") => parse_toml_replacements(&contents, strict_format, coerce_types),
+        Some("yml" | "yaml") => parse_yaml_replacements(&contents, strict_format, coerce_types),
+        _ => parse_replacements(&contents, strict_format, coerce_types),
+    };
+
+    Ok(Arc::new(map?))
+}
+
+/// Reads every replacements file like [`read_replacements`] and merges them in order, values of
+/// later files overriding earlier ones
+pub async fn read_replacements_files(
+    paths: &[PathBuf],
+    strict_format: bool,
+    coerce_types: bool,
+    strip_comments: bool,
+) -> Result<ReplacementsMap, anyhow::Error> {
+    let mut replacements_map = ReplacementsMap::default();
+
+    for path in paths {
+        let file_map = read_replacements(path.clone(), strict_format, coerce_types, strip_comments)
+            .await
+            .map_err(|err| anyhow!("Failed to read replacements file {:?}: {}", path, err))?;
+
+        replacements_map.merge((*file_map).clone(), MergeStrategy::Override);
+    }
+
+    Ok(replacements_map)
+}
+
+/// Turns a replacement value into the text substituted for its placeholder. Booleans and numbers
+/// become quoted strings (`"true"`), or JavaScript literals (`true`) with `coerce_types`
+fn replacement_value(
+    key: &str,
+    value: serde_json::Value,
+    coerce_types: bool,
+) -> Result<String, anyhow::Error> {
+    match value {
+        serde_json::Value::String(value) => Ok(value),
+        value @ (serde_json::Value::Bool(_) | serde_json::Value::Number(_)) => {
+            if coerce_types {
+                Ok(value.to_string())
+            } else {
+                Ok(serde_json::Value::String(value.to_string()).to_string())
+            }
+        }
+        value => Err(anyhow!(
+            "Replacement {} must be a string, boolean or number, found {}",
+            key,
+            value
+        )),
+    }
+}
+
+/// Parses a JSON object mapping placeholder names to their values
+pub fn parse_replacements(
+    contents: &str,
+    strict_format: bool,
+    coerce_types: bool,
+) -> Result<ReplacementsMap, anyhow::Error> {
+    let raw_map = serde_json::from_str::<HashMap<String, serde_json::Value>>(contents)
+        .map_err(|_| anyhow!("Invalid JSON format"))?;
+
+    replacements_from_values(raw_map, strict_format, coerce_types)
+}
+
+/// Parses the flat `[replacements]` table of a TOML file mapping placeholder names to their values
+pub fn parse_toml_replacements(
+    contents: &str,
+    strict_format: bool,
+    coerce_types: bool,
+) -> Result<ReplacementsMap, anyhow::Error> {
+    let raw_map = Config::builder()
+        .add_source(config::File::from_str(contents, config::FileFormat::Toml))
+        .build()
+        .and_then(|config| config.get::<HashMap<String, serde_json::Value>>("replacements"))
+        .map_err(|err| match err {
+            ConfigError::NotFound(_) => {
+                anyhow!("Invalid TOML format, expected a [replacements] table")
+            }
+            err => anyhow!("Invalid TOML format \n Error: {:?}", err),
+        })?;
+
+    replacements_from_values(raw_map, strict_format, coerce_types)
+}
+
+/// Parses a flat YAML mapping of placeholder names to their values
+pub fn parse_yaml_replacements(
+    contents: &str,
+    strict_format: bool,
+    coerce_types: bool,
+) -> Result<ReplacementsMap, anyhow::Error> {
+    let raw_map = serde_yaml::from_str::<HashMap<String, serde_json::Value>>(contents)
+        .map_err(|err| anyhow!("Invalid YAML format \n Error: {:?}", err))?;
+
+    replacements_from_values(raw_map, strict_format, coerce_types)
+}
+
+/// Checks the keys of a parsed replacements file and converts its values
+fn replacements_from_values(
+    raw_map: HashMap<String, serde_json::Value>,
+    strict_format: bool,
+    coerce_types: bool,
+) -> Result<ReplacementsMap, anyhow::Error> {
+    let raw_map = raw_map
+        .into_iter()
+        .map(|(key, value)| {
+            let value = replacement_value(&key, value, coerce_types)?;
+
+            Ok((key, value))
+        })
+        .collect::<Result<HashMap<String, String>, anyhow::Error>>()?;
+
+    if strict_format {
+        let mut invalid_keys = raw_map
+            .keys()
+            .filter(|key| !is_strict_placeholder_key(key))
+            .cloned()
+            .collect::<Vec<String>>();
+
+        if !invalid_keys.is_empty() {
+            invalid_keys.sort();
+
+            return Err(anyhow!(
+                "Replacement keys must only contain uppercase letters, digits and underscores and start with a letter (e.g. REPL_API_URL). Invalid keys: {}",
+                invalid_keys.join(", ")
+            ));
+        }
+    }
+
+    let mut raw_map = ReplacementsMap(raw_map);
+
+    // files shared with CI may set REPL_ACCOUNT, the loader resolves it to the account itself
+    for key in raw_map.contains_reserved() {
+        eprintln!(
+            "Warning: ignoring the reserved {} key of the replacements file",
+            key
+        );
+        raw_map.0.remove(&key);
+    }
+
+    let mut map = ReplacementsMap::default();
+    for (key, value) in raw_map.0 {
+        map.insert(key, value)?;
+    }
+
+    Ok(map)
+}
+
+/// Reads the `[replacements]` table of a config file, returning an empty map when there is none.
+/// Values are handled like those of a replacements file, see `replacements_from_values`
+pub fn from_config_builder(
+    config: &Config,
+    strict_format: bool,
+    coerce_types: bool,
+) -> Result<ReplacementsMap, anyhow::Error> {
+    // an array lists replacement files instead, see `replacement_files_from_config`
+    if config.get::<Vec<config::Value>>("replacements").is_ok() {
+        return Ok(ReplacementsMap::default());
+    }
+
+    let raw_map = match config.get::<HashMap<String, serde_json::Value>>("replacements") {
+        Ok(raw_map) => raw_map,
+        Err(ConfigError::NotFound(_)) => return Ok(ReplacementsMap::default()),
+        Err(err) => {
+            return Err(anyhow!(
+                "Invalid [replacements] table in config file \n Error: {:?}",
+                err
+            ))
+        }
+    };
+
+    replacements_from_values(raw_map, strict_format, coerce_types)
+}
+
+/// Replacement files listed as a `replacements = ["base.json", "staging.json"]` array in the
+/// config file, read before the `--replacements` files. A `[replacements]` table lists none
+pub fn replacement_files_from_config(config: &Config) -> Vec<PathBuf> {
+    config
+        .get::<Vec<PathBuf>>("replacements")
+        .unwrap_or_default()
+}
+
+/// Prefix marking a replacement value as base64 encoded AES-256-GCM ciphertext
+pub const ENCRYPTED_VALUE_PREFIX: &str = "enc:";
+
+/// Length of the nonce prepended to the ciphertext of encrypted values
+const NONCE_LENGTH: usize = 12;
+
+/// Parses a 32 byte decryption key written as 64 hex characters
+pub fn parse_decryption_key(hex: &str) -> Result<[u8; 32], anyhow::Error> {
+    let hex = hex.trim();
+
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(anyhow!(
+            "The replacements key must be 32 bytes written as 64 hex characters"
+        ));
+    }
+
+    let mut key = [0; 32];
+    for (index, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16)
+            .map_err(|_| anyhow!("The replacements key contains non hex characters"))?;
+    }
+
+    Ok(key)
+}
+
+/// Decrypts every value prefixed with `enc:` in place, other values are left as plaintext.
+/// The base64 encoded payload is the 12 byte nonce followed by the ciphertext.
+pub fn decrypt_values(
+    replacements_map: &mut ReplacementsMap,
+    key: &[u8; 32],
+) -> Result<(), anyhow::Error> {
+    let cipher = Aes256Gcm::new(key.into());
+
+    for (name, value) in replacements_map.0.iter_mut() {
+        let Some(encoded) = value.strip_prefix(ENCRYPTED_VALUE_PREFIX) else {
+            continue;
+        };
+
+        let payload = BASE64.decode(encoded).map_err(|err| {
+            anyhow!(
+                "Invalid base64 in encrypted value of {} \n Error: {:?}",
+                name,
+                err
+            )
+        })?;
+
+        if payload.len() < NONCE_LENGTH {
+            return Err(anyhow!("Encrypted value of {} is too short", name));
+        }
+
+        let (nonce, ciphertext) = payload.split_at(NONCE_LENGTH);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Failed to decrypt value of {}, is the key correct?", name))?;
+
+        *value = String::from_utf8(plaintext)
+            .map_err(|_| anyhow!("Decrypted value of {} is not valid UTF-8", name))?;
+    }
+
+    Ok(())
+}
+
+/// Keeps roughly the first third of a value (at most 4 characters) visible
+pub(crate) fn redact_value(value: &str) -> String {
+    let visible = (value.chars().count() / 3).min(4);
+
+    format!("{}****", value.chars().take(visible).collect::<String>())
+}
+
+/// Builds a Markdown table listing each replacement key, its redacted value and description
+pub(crate) fn replacements_template(
+    replacements_map: &ReplacementsMap,
+    descriptions: &HashMap<String, String>,
+) -> String {
+    let mut keys = replacements_map.keys().collect::<Vec<&String>>();
+    keys.sort();
+
+    let mut template = String::from("| Key | Value | Description |\n| --- | --- | --- |\n");
+
+    for key in keys {
+        let description = descriptions.get(key).map(String::as_str).unwrap_or("");
+
+        template.push_str(&format!(
+            "| `{}` | `{}` | {} |\n",
+            key,
+            redact_value(&replacements_map.0[key]).replace('|', "\\|"),
+            description.replace('|', "\\|")
+        ));
+    }
+
+    template
+}
+
+/// Writes the replacements documentation table, reading descriptions from a
+/// `<name>.descriptions.json` file next to each replacements file when present, with
+/// descriptions of later files overriding earlier ones
+pub async fn write_replacements_template(
+    replacements_paths: &[PathBuf],
+    replacements_map: &ReplacementsMap,
+    output_path: &Path,
+) -> Result<(), anyhow::Error> {
+    let mut descriptions = HashMap::new();
+
+    for replacements_path in replacements_paths {
+        let descriptions_path = replacements_path.with_extension("descriptions.json");
+
+        if !descriptions_path.exists() {
+            continue;
+        }
+
+        let contents = fs::read_to_string(&descriptions_path)
+            .await
+            .map_err(|err| {
+                anyhow!(
+                    "Failed to read path {:?} \n Error: {:?}",
+                    descriptions_path,
+                    err
+                )
+            })?;
+
+        descriptions.extend(
+            serde_json::from_str::<HashMap<String, String>>(&contents).map_err(|_| {
+                anyhow!(
+                    "Invalid JSON format in descriptions file {:?}",
+                    descriptions_path
+                )
+            })?,
+        );
+    }
+
+    fs::write(
+        output_path,
+        replacements_template(replacements_map, &descriptions),
+    )
+    .await
+    .map_err(|err| anyhow!("Failed to write file {:?} \n Error: {:?}", output_path, err))
+}
+
+/// Builds a JSON Schema of an object requiring every replacement key as a string property
+pub(crate) fn replacements_schema(replacements_map: &ReplacementsMap) -> serde_json::Value {
+    let mut keys = replacements_map.keys().collect::<Vec<&String>>();
+    keys.sort();
+
+    let properties = keys
+        .iter()
+        .map(|key| (key.to_string(), json!({ "type": "string" })))
+        .collect::<serde_json::Map<String, serde_json::Value>>();
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Replacements",
+        "type": "object",
+        "properties": properties,
+        "required": keys,
+    })
+}
+
+/// Writes the JSON Schema of the replacement keys, see [`replacements_schema`]
+pub async fn write_replacements_schema(
+    replacements_map: &ReplacementsMap,
+    output_path: &Path,
+) -> Result<(), anyhow::Error> {
+    let schema =
+        serde_json::to_string_pretty(&replacements_schema(replacements_map)).map_err(|err| {
+            anyhow!(
+                "Failed to serialize replacements schema \n Error: {:?}",
+                err
+            )
+        })?;
+
+    fs::write(output_path, schema)
+        .await
+        .map_err(|err| anyhow!("Failed to write file {:?} \n Error: {:?}", output_path, err))
+}
+
+/// `${KEY}` placeholders of any valid key, capturing the key
+static PLACEHOLDER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\$\{([^\s${}]+)\}").unwrap());
+
+/// `${REPL_*}` placeholders, capturing the key
+static REPL_PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$\{(REPL_[A-Za-z0-9_]*)\}").unwrap());
+
+/// The distinct `REPL_*` placeholder keys used in `codes`, sorted, without the reserved keys
+pub fn placeholder_keys<'a>(codes: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut keys = codes
+        .into_iter()
+        .flat_map(|code| REPL_PLACEHOLDER.captures_iter(code))
+        .map(|captures| captures[1].to_string())
+        .filter(|key| !RESERVED_KEYS.contains(&key.as_str()))
+        .collect::<Vec<String>>();
+    keys.sort();
+    keys.dedup();
+
+    keys
+}
+
+/// Writes a replacements file with an empty value for each of `keys`, to be filled in
+pub async fn write_replacements_skeleton(
+    keys: &[String],
+    output_path: &Path,
+) -> Result<(), anyhow::Error> {
+    let skeleton = keys
+        .iter()
+        .map(|key| (key.clone(), json!("")))
+        .collect::<serde_json::Map<String, serde_json::Value>>();
+
+    let json = serde_json::to_string_pretty(&skeleton)
+        .map_err(|err| anyhow!("Failed to serialize replacements \n Error: {:?}", err))?;
+
+    fs::write(output_path, json)
+        .await
+        .map_err(|err| anyhow!("Failed to write file {:?} \n Error: {:?}", output_path, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replacements_map(pairs: &[(&str, &str)]) -> ReplacementsMap {
+        let mut map = ReplacementsMap::default();
+
+        for (key, value) in pairs {
+            map.insert(key.to_string(), value.to_string()).unwrap();
+        }
+
+        map
+    }
+
+    #[test]
+    fn test_replace_placeholders() {
+        let input_string = String::from("<div> This is ${REPL_PLACEHOLDER1} </div> <Widget src=\"${REPL_ACCOUNT}/widget/SomeWidget\"> <div>${REPL_PLACEHOLDER2}</div>");
+        let expected_output = String::from("<div> This is value1 </div> <Widget src=\"MY_ACCOUNT/widget/SomeWidget\"> <div>value2</div>");
+
+        let replacements = replacements_map(&[
+            ("REPL_PLACEHOLDER1", "value1"),
+            ("REPL_PLACEHOLDER2", "value2"),
+        ]);
+
+        let (modified_string, _) =
+            replace_placeholders(&input_string, "MY_ACCOUNT", &replacements, true);
+
+        assert_eq!(modified_string, expected_output);
+    }
+
+    #[test]
+    fn test_replace_placeholders_substitutions() {
+        let input_string = String::from("${REPL_URL}/a ${REPL_URL}/b ${REPL_ACCOUNT}");
+
+        let replacements =
+            replacements_map(&[("REPL_URL", "https://near.org"), ("REPL_UNUSED", "unused")]);
+
+        let (_, stats) = replace_placeholders(&input_string, "MY_ACCOUNT", &replacements, true);
+        let mut substitutions = stats.substitutions;
+        substitutions.sort_by(|a, b| a.placeholder.cmp(&b.placeholder));
+
+        assert_eq!(stats.keys_used, vec!["REPL_ACCOUNT", "REPL_URL"]);
+        assert_eq!(stats.keys_unused, vec!["REPL_UNUSED"]);
+        assert_eq!(stats.total_substitutions, 3);
+        assert_eq!(
+            substitutions,
+            vec![
+                Substitution {
+                    placeholder: "${REPL_ACCOUNT}".to_owned(),
+                    replaced_with: "MY_ACCOUNT".to_owned(),
+                    occurrences: 1,
+                },
+                Substitution {
+                    placeholder: "${REPL_URL}".to_owned(),
+                    replaced_with: "https://near.org".to_owned(),
+                    occurrences: 2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replace_placeholders_empty_map() {
+        let input_string = String::from("<div> This is ${REPL_PLACEHOLDER1} </div> <Widget src=\"${REPL_ACCOUNT}/widget/SomeWidget\"> <div>${REPL_PLACEHOLDER2}</div>");
+        let expected_output = String::from("<div> This is ${REPL_PLACEHOLDER1} </div> <Widget src=\"MY_ACCOUNT/widget/SomeWidget\"> <div>${REPL_PLACEHOLDER2}</div>");
+
+        let (modified_string, _) = replace_placeholders(
+            &input_string,
+            "MY_ACCOUNT",
+            &ReplacementsMap::default(),
+            true,
+        );
+
+        assert_eq!(modified_string, expected_output);
+    }
+
+    #[test]
+    fn test_replace_placeholders_no_repl_account_injection() {
+        let input_string = String::from("<Widget src=\"${REPL_ACCOUNT}/widget/SomeWidget\" />");
+
+        let (modified_string, _) = replace_placeholders(
+            &input_string,
+            "MY_ACCOUNT",
+            &ReplacementsMap::default(),
+            false,
+        );
+
+        assert_eq!(modified_string, input_string);
+    }
+
+    #[test]
+    fn test_replace_placeholders_wrong_notation() {
+        let input_string =
+            String::from("${REPL_ACCOUNT REPL_ACCOUNT $REPL_ACCOUNT ${WRONG_PLACEHOLDER}");
+        let expected_output = input_string.clone();
+
+        let replacements = replacements_map(&[
+            ("REPL_PLACEHOLDER1", "value1"),
+            ("REPL_PLACEHOLDER2", "value2"),
+        ]);
+
+        let (modified_string, _) =
+            replace_placeholders(&input_string, "MY_ACCOUNT", &replacements, true);
+
+        assert_eq!(modified_string, expected_output);
+    }
+
+    #[tokio::test]
+    async fn test_read_replacements() {
+        let path: PathBuf = "./test/replacements.json".into();
+
+        let expected_output = replacements_map(&[
+            ("REPL_PLACEHOLDER1", "value1"),
+            ("REPL_PLACEHOLDER2", "value2"),
+        ]);
+
+        let map = read_replacements(path, false, false, false).await.unwrap();
+
+        assert_eq!(*map, expected_output);
+    }
+
+    #[test]
+    fn test_replacements_template() {
+        let replacements = replacements_map(&[
+            ("REPL_API_URL", "https://api.near.org"),
+            ("REPL_ACCOUNT_ID", "ab"),
+        ]);
+        let descriptions: HashMap<String, String> =
+            vec![("REPL_API_URL".to_owned(), "Base URL of the API".to_owned())]
+                .into_iter()
+                .collect();
+
+        assert_eq!(
+            replacements_template(&replacements, &descriptions),
+            "| Key | Value | Description |\n\
+             | --- | --- | --- |\n\
+             | `REPL_ACCOUNT_ID` | `****` |  |\n\
+             | `REPL_API_URL` | `http****` | Base URL of the API |\n"
+        );
+    }
+
+    #[test]
+    fn test_replacements_schema() {
+        let replacements = replacements_map(&[("REPL_B", "b"), ("REPL_A", "https://api.near.org")]);
+
+        assert_eq!(
+            replacements_schema(&replacements),
+            json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "title": "Replacements",
+                "type": "object",
+                "properties": {
+                    "REPL_A": { "type": "string" },
+                    "REPL_B": { "type": "string" },
+                },
+                "required": ["REPL_A", "REPL_B"],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_replacements_strict_format() {
+        read_replacements("./test/replacements.json".into(), true, false, false)
+            .await
+            .unwrap();
+
+        let err = read_replacements(
+            "./test/replacements.invalid-format.json".into(),
+            true,
+            false,
+            false,
+        )
+        .await
+        .unwrap_err();
+
+        assert!(err
+            .to_string()
+            .ends_with("Invalid keys: 2REPL_PLACEHOLDER, repl_placeholder1"));
+    }
+
+    #[tokio::test]
+    async fn test_read_replacements_repl_account() {
+        let path: PathBuf = "./test/replacements.wrong.json".into();
+
+        assert_eq!(
+            *read_replacements(path, false, false, false).await.unwrap(),
+            replacements_map(&[("REPL_PLACEHOLDER2", "value2")])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merge_inline_replacements() {
+        let mut map = (*read_replacements("./test/replacements.json".into(), false, false, false)
+            .await
+            .unwrap())
+        .clone();
+        let inline_map = parse_replacements(
+            r#"{"REPL_PLACEHOLDER2": "inline2", "REPL_PLACEHOLDER3": "inline3"}"#,
+            false,
+            false,
+        )
+        .unwrap();
+
+        let mut keep_existing = map.clone();
+        keep_existing.merge(inline_map.clone(), MergeStrategy::KeepExisting);
+        map.merge(inline_map, MergeStrategy::Override);
+
+        assert_eq!(
+            map,
+            replacements_map(&[
+                ("REPL_PLACEHOLDER1", "value1"),
+                ("REPL_PLACEHOLDER2", "inline2"),
+                ("REPL_PLACEHOLDER3", "inline3"),
+            ])
+        );
+        assert_eq!(
+            keep_existing,
+            replacements_map(&[
+                ("REPL_PLACEHOLDER1", "value1"),
+                ("REPL_PLACEHOLDER2", "value2"),
+                ("REPL_PLACEHOLDER3", "inline3"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_replacements_map_insert() {
+        let mut map = ReplacementsMap::default();
+
+        map.insert("REPL_URL".to_string(), "https://near.org".to_string())
+            .unwrap();
+        assert!(map
+            .insert("REPL_ACCOUNT".to_string(), "alice.near".to_string())
+            .is_err());
+        assert!(map
+            .insert("REPL URL}".to_string(), "https://near.org".to_string())
+            .is_err());
+
+        assert_eq!(map.get("REPL_URL"), Some(&"https://near.org".to_string()));
+        assert_eq!(map.len(), 1);
+        assert!(map.contains_reserved().is_empty());
+        assert_eq!(
+            ReplacementsMap(
+                vec![("REPL_ACCOUNT".to_string(), "alice.near".to_string())]
+                    .into_iter()
+                    .collect()
+            )
+            .contains_reserved(),
+            vec!["REPL_ACCOUNT".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_decrypt_values() {
+        let key = parse_decryption_key(
+            "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+        )
+        .unwrap();
+        let nonce = [7; NONCE_LENGTH];
+        let mut payload = nonce.to_vec();
+        payload.extend(
+            Aes256Gcm::new(&key.into())
+                .encrypt(Nonce::from_slice(&nonce), b"secret".as_ref())
+                .unwrap(),
+        );
+        let encrypted = format!("{}{}", ENCRYPTED_VALUE_PREFIX, BASE64.encode(payload));
+
+        let mut map =
+            replacements_map(&[("REPL_SECRET", encrypted.as_str()), ("REPL_PLAIN", "plain")]);
+        decrypt_values(&mut map, &key).unwrap();
+
+        assert_eq!(
+            map,
+            replacements_map(&[("REPL_SECRET", "secret"), ("REPL_PLAIN", "plain")])
+        );
+
+        let mut map = replacements_map(&[("REPL_SECRET", encrypted.as_str())]);
+        assert!(decrypt_values(&mut map, &[0; 32]).is_err());
+        assert!(parse_decryption_key("abcd").is_err());
+    }
+
+    #[test]
+    fn test_unused_replacement_keys() {
+        let replacements = replacements_map(&[
+            ("REPL_URL", "https://near.org"),
+            ("REPL_STALE", "stale"),
+            ("REPL_OLD", "old"),
+        ]);
+        let (_, stats) = replace_placeholders(
+            "${REPL_URL} ${REPL_ACCOUNT}",
+            "MY_ACCOUNT",
+            &replacements,
+            true,
+        );
+
+        assert_eq!(
+            unused_replacement_keys(&replacements, &stats.substitutions),
+            vec!["REPL_OLD".to_string(), "REPL_STALE".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_replacements_from_env() {
+        let vars = vec![
+            ("REPL_API_URL", "https://api.near.org"),
+            ("REPL_ACCOUNT", "alice.near"),
+            ("REPL_DECRYPT_KEY", "00"),
+            ("HOME", "/root"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+
+        assert_eq!(
+            replacements_from_env(vars),
+            replacements_map(&[("REPL_API_URL", "https://api.near.org")])
+        );
+    }
+
+    #[test]
+    fn test_from_config_builder() {
+        let config = Config::builder()
+            .add_source(config::File::from_str(
+                "[replacements]\nREPL_API_URL = \"https://api.near.org\"\nREPL_LIMIT = 10\n",
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            from_config_builder(&config, false, false).unwrap(),
+            replacements_map(&[
+                ("REPL_API_URL", "https://api.near.org"),
+                ("REPL_LIMIT", "\"10\"")
+            ])
+        );
+        assert_eq!(
+            from_config_builder(&config, false, true).unwrap(),
+            replacements_map(&[
+                ("REPL_API_URL", "https://api.near.org"),
+                ("REPL_LIMIT", "10")
+            ])
+        );
+
+        let config = Config::builder()
+            .add_source(config::File::from_str(
+                "[replacements]\nREPL_ACCOUNT = \"bob.near\"\nREPL_API_URL = \"https://api.near.org\"\n",
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        assert_eq!(
+            from_config_builder(&config, false, false).unwrap(),
+            replacements_map(&[("REPL_API_URL", "https://api.near.org")])
+        );
+
+        let config = Config::builder()
+            .add_source(config::File::from_str(
+                "[replacements]\nrepl_api_url = \"https://api.near.org\"\n",
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        assert!(from_config_builder(&config, true, false).is_err());
+
+        let config = Config::builder().build().unwrap();
+        assert!(from_config_builder(&config, false, false)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_replacement_files_from_config() {
+        let config = Config::builder()
+            .add_source(config::File::from_str(
+                "replacements = [\"base.json\", \"staging.yaml\"]\n",
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            replacement_files_from_config(&config),
+            vec![PathBuf::from("base.json"), PathBuf::from("staging.yaml")]
+        );
+        assert!(from_config_builder(&config, false, false)
+            .unwrap()
+            .is_empty());
+
+        let config = Config::builder()
+            .add_source(config::File::from_str(
+                "[replacements]\nREPL_API_URL = \"https://api.near.org\"\n",
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        assert!(replacement_files_from_config(&config).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_read_replacements_files() {
+        let replacements = read_replacements_files(
+            &[
+                "./test/replacements.json".into(),
+                "./test/replacements.override.json".into(),
+            ],
+            false,
+            false,
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            replacements,
+            replacements_map(&[
+                ("REPL_PLACEHOLDER1", "value1"),
+                ("REPL_PLACEHOLDER2", "overridden"),
+                ("REPL_STAGING", "true"),
+            ])
+        );
+
+        let err = read_replacements_files(
+            &[
+                "./test/replacements.json".into(),
+                "./test/missing.json".into(),
+            ],
+            false,
+            false,
+            false,
+        )
+        .await
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Failed to read replacements file \"./test/missing.json\""));
+    }
+
+    #[tokio::test]
+    async fn test_read_replacements_strip_comments() {
+        let path: PathBuf = "./test/replacements.comments.json".into();
+
+        assert!(read_replacements(path.clone(), false, false, false)
+            .await
+            .is_err());
+        assert_eq!(
+            *read_replacements(path, false, false, true).await.unwrap(),
+            replacements_map(&[
+                ("REPL_API_URL", "https://api.near.org//v1"),
+                ("REPL_PLACEHOLDER2", "value2")
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_yaml_replacements() {
+        let replacements = read_replacements("./test/replacements.yaml".into(), true, false, false)
+            .await
+            .unwrap();
+
+        // REPL_ACCOUNT is skipped with a warning, like in JSON files
+        assert_eq!(
+            *replacements,
+            replacements_map(&[
+                ("REPL_PLACEHOLDER1", "value1"),
+                ("REPL_PLACEHOLDER2", "value2"),
+                ("REPL_LIMIT", "\"10\"")
+            ])
+        );
+
+        assert_eq!(
+            parse_yaml_replacements("REPL_A: a\n", false, false).unwrap(),
+            replacements_map(&[("REPL_A", "a")])
+        );
+        assert!(parse_yaml_replacements("- REPL_A\n", false, false).is_err());
+        assert!(parse_yaml_replacements("REPL_A:\n  nested: a\n", false, false).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_toml_replacements() {
+        let replacements = read_replacements("./test/replacements.toml".into(), true, false, false)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *replacements,
+            replacements_map(&[
+                ("REPL_PLACEHOLDER1", "value1"),
+                ("REPL_PLACEHOLDER2", "value2"),
+                ("REPL_LIMIT", "\"10\"")
+            ])
+        );
+
+        let err = parse_toml_replacements("REPL_A = \"a\"\n", false, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid TOML format, expected a [replacements] table"
+        );
+    }
+
+    #[test]
+    fn test_parse_replacements_coerce_types() {
+        let contents = r#"{"REPL_ENABLED": true, "REPL_LIMIT": 42, "REPL_NAME": "near"}"#;
+
+        assert_eq!(
+            parse_replacements(contents, false, false).unwrap(),
+            replacements_map(&[
+                ("REPL_ENABLED", "\"true\""),
+                ("REPL_LIMIT", "\"42\""),
+                ("REPL_NAME", "near"),
+            ])
+        );
+        assert_eq!(
+            parse_replacements(contents, false, true).unwrap(),
+            replacements_map(&[
+                ("REPL_ENABLED", "true"),
+                ("REPL_LIMIT", "42"),
+                ("REPL_NAME", "near"),
+            ])
+        );
+        assert!(parse_replacements(r#"{"REPL_LIST": [1]}"#, false, true).is_err());
+    }
+
+    #[test]
+    fn test_replacements_from_env_prefix() {
+        let vars = vec![
+            ("APP_API_URL", "https://api.near.org"),
+            ("APP_REPL_ACCOUNT", "alice.near"),
+            ("APP_", "empty"),
+            ("HOME", "/root"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+
+        assert_eq!(
+            replacements_from_env_prefix(vars, "APP_").unwrap(),
+            replacements_map(&[("API_URL", "https://api.near.org")])
+        );
+        assert!(replacements_from_env_prefix(vec![], "").is_err());
+    }
+
+    #[test]
+    fn test_replacements_from_kv() {
+        let pairs = [
+            "REPL_API_URL=https://api.near.org?a=b",
+            "REPL_NAME=",
+            "REPL_NAME=near",
+        ]
+        .map(String::from);
+
+        assert_eq!(
+            replacements_from_kv(&pairs).unwrap(),
+            replacements_map(&[
+                ("REPL_API_URL", "https://api.near.org?a=b"),
+                ("REPL_NAME", "near")
+            ])
+        );
+        assert!(replacements_from_kv(&["REPL_API_URL".to_string()]).is_err());
+        assert!(replacements_from_kv(&["REPL_ACCOUNT=alice.near".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_resolve_references_chain() {
+        let map = replacements_map(&[
+            ("REPL_HOST", "api.near.org"),
+            ("REPL_BASE", "https://${REPL_HOST}"),
+            (
+                "REPL_POSTS",
+                "${REPL_BASE}/posts?by=${REPL_ACCOUNT}&v=${REPL_MISSING}",
+            ),
+        ]);
+
+        assert_eq!(
+            map.resolve_references().unwrap(),
+            replacements_map(&[
+                ("REPL_HOST", "api.near.org"),
+                ("REPL_BASE", "https://api.near.org"),
+                (
+                    "REPL_POSTS",
+                    "https://api.near.org/posts?by=${REPL_ACCOUNT}&v=${REPL_MISSING}"
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_resolve_references_diamond() {
+        let map = replacements_map(&[
+            ("REPL_ROOT", "near"),
+            ("REPL_LEFT", "${REPL_ROOT}.left"),
+            ("REPL_RIGHT", "${REPL_ROOT}.right"),
+            ("REPL_BOTH", "${REPL_LEFT}+${REPL_RIGHT}+${REPL_LEFT}"),
+        ]);
+
+        assert_eq!(
+            map.resolve_references().unwrap().get("REPL_BOTH").unwrap(),
+            "near.left+near.right+near.left"
+        );
+    }
+
+    #[test]
+    fn test_resolve_references_cycle() {
+        let map = replacements_map(&[
+            ("REPL_A", "${REPL_B}"),
+            ("REPL_B", "x${REPL_C}"),
+            ("REPL_C", "${REPL_A}"),
+            ("REPL_OK", "ok"),
+        ]);
+
+        assert_eq!(
+            map.resolve_references().unwrap_err().to_string(),
+            "Replacement values reference each other in a cycle: REPL_A -> REPL_B -> REPL_C -> REPL_A"
+        );
+
+        let map = replacements_map(&[("REPL_SELF", "${REPL_SELF}/path")]);
+        assert!(map.resolve_references().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_replacements_skeleton() {
+        let keys = placeholder_keys([
+            "<Widget src=\"${REPL_ACCOUNT}/widget/Feed\" props={{ url: \"${REPL_API_URL}\" }} />",
+            "${REPL_API_URL}/posts ${REPL_TITLE} ${OTHER} $REPL_BARE",
+        ]);
+        assert_eq!(keys, vec!["REPL_API_URL", "REPL_TITLE"]);
+
+        let path = std::env::temp_dir().join("bos-loader-test-replacements-skeleton.json");
+        write_replacements_skeleton(&keys, &path).await.unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&path).await.unwrap())
+                .unwrap(),
+            json!({ "REPL_API_URL": "", "REPL_TITLE": "" })
+        );
+    }
+
+    #[test]
+    fn test_invalid_url_values() {
+        let mut replacements_map = ReplacementsMap::default();
+        for (key, value) in [
+            ("REPL_API_URL", "https://api.near.org/v1"),
+            ("REPL_TYPO", "htps://api.near.org"),
+            ("REPL_SLASH", "https:/api.near.org"),
+            ("REPL_SPACE", "http://api near.org"),
+            ("REPL_NAME", "near"),
+        ] {
+            replacements_map
+                .insert(key.to_string(), value.to_string())
+                .unwrap();
+        }
+
+        let invalid = invalid_url_values(&replacements_map);
+
+        assert_eq!(invalid.len(), 3);
+        assert!(invalid[0].starts_with("REPL_SLASH has a malformed URL"));
+        assert!(invalid[0].ends_with("expected :// after the scheme"));
+        assert!(invalid[1].starts_with("REPL_SPACE"));
+        assert!(invalid[2].ends_with("unsupported scheme \"htps\""));
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::anyhow;
+use serde::Deserialize;
+use serde_json::json;
+
+/// Default endpoint of `--rpc-url`
+pub const DEFAULT_RPC_URL: &str = "https://rpc.mainnet.near.org";
+
+#[derive(Deserialize)]
+struct RpcErrorCause {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    cause: Option<RpcErrorCause>,
+    #[serde(default)]
+    data: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    result: Option<serde_json::Value>,
+    error: Option<RpcError>,
+}
+
+/// Queries a NEAR JSON-RPC endpoint (e.g. `https://rpc.mainnet.near.org`)
+#[derive(Clone, Debug)]
+pub struct RpcClient {
+    rpc_url: String,
+    client: reqwest::Client,
+}
+
+impl RpcClient {
+    pub fn new(rpc_url: &str) -> Self {
+        Self {
+            rpc_url: rpc_url.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Checks whether `account` exists on-chain with a `view_account` query at final finality
+    pub async fn account_exists(&self, account: &str) -> Result<bool, anyhow::Error> {
+        let response = self
+            .client
+            .post(&self.rpc_url)
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": "bos-loader",
+                "method": "query",
+                "params": {
+                    "request_type": "view_account",
+                    "finality": "final",
+                    "account_id": account,
+                },
+            }))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| {
+                anyhow!(
+                    "Failed to query account {} from {} \n Error: {:?}",
+                    account,
+                    self.rpc_url,
+                    err
+                )
+            })?;
+
+        let response = response.json::<RpcResponse>().await.map_err(|err| {
+            anyhow!(
+                "Invalid response from RPC {} \n Error: {:?}",
+                self.rpc_url,
+                err
+            )
+        })?;
+
+        match (response.result, response.error) {
+            (Some(_), None) => Ok(true),
+            (
+                _,
+                Some(RpcError {
+                    cause: Some(cause), ..
+                }),
+            ) if cause.name == "UNKNOWN_ACCOUNT" => Ok(false),
+            (_, Some(error)) => Err(anyhow!(
+                "RPC {} failed to view account {} \n Error: {}",
+                self.rpc_url,
+                account,
+                error.data
+            )),
+            (None, None) => Err(anyhow!(
+                "Empty response from RPC {} for account {}",
+                self.rpc_url,
+                account
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::Filter;
+
+    #[tokio::test]
+    async fn test_account_exists() {
+        let rpc = warp::post()
+            .and(warp::body::json())
+            .map(|body: serde_json::Value| {
+                assert_eq!(body["method"], "query");
+                assert_eq!(body["params"]["request_type"], "view_account");
+
+                match body["params"]["account_id"].as_str().unwrap() {
+                    "alice.near" => warp::reply::json(&json!({
+                        "jsonrpc": "2.0",
+                        "id": "bos-loader",
+                        "result": { "amount": "1", "block_height": 1 }
+                    })),
+                    account => warp::reply::json(&json!({
+                        "jsonrpc": "2.0",
+                        "id": "bos-loader",
+                        "error": {
+                            "name": "HANDLER_ERROR",
+                            "cause": {
+                                "name": "UNKNOWN_ACCOUNT",
+                                "info": { "requested_account_id": account }
+                            },
+                            "data": format!("account {account} does not exist while viewing")
+                        }
+                    })),
+                }
+            });
+        let (address, server) = warp::serve(rpc).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let client = RpcClient::new(&format!("http://{address}"));
+
+        assert!(client.account_exists("alice.near").await.unwrap());
+        assert!(!client.account_exists("alcie.near").await.unwrap());
+    }
+}
@@ -0,0 +1,3054 @@
+use crate::{
+    access_log::{rejection_status, with_access_log, AccessLog},
+    accounts::{validate_account_id, AccountPath, AccountRegistry},
+    cache::{tree_timestamps, CacheState},
+    cli::ResolvedConfig,
+    components::{
+        fetch_remote_components, find_entry_point, format_component_tree, format_size_histogram,
+        handle_request, keys_not_matching, load_components_from_tar_with_options,
+        load_test_mode_components, size_histogram, write_audit_log, write_component_stats,
+        AccountMetadata, ComponentCode, FileList, FileListMetadata, HandleRequestOptions,
+        LoadReport,
+    },
+    key::ComponentKey,
+    metrics::{with_metrics, Metrics},
+    path::ComponentPathResolver,
+    replacements::{unused_replacement_keys, ReplacementsMap},
+    snapshot::Snapshot,
+    watch::{
+        append_watch_events, diff_components, log_watch_event, next_changes, run_reload_script,
+        watch_channel, watch_component_files, watch_files, watch_trigger_file, PendingRemovals,
+        WatchEvent, WATCH_EVENT_CAPACITY,
+    },
+};
+use anyhow::anyhow;
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Instant, SystemTime},
+};
+use tokio::{
+    fs,
+    sync::{broadcast, watch, Mutex, RwLock},
+    task::{JoinHandle, JoinSet},
+};
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+use warp::{
+    http::{
+        header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE, ETAG},
+        HeaderValue, Method, StatusCode,
+    },
+    reply::Response,
+    Filter,
+};
+
+#[derive(Clone)]
+pub(crate) struct RoutesOptions {
+    accounts: Arc<RwLock<AccountRegistry>>,
+    config: Arc<ResolvedConfig>,
+    /// The replacements components are loaded with, swapped by `--replacements-watch`
+    replacements_map: Arc<watch::Sender<Arc<ReplacementsMap>>>,
+    component_count: Arc<AtomicUsize>,
+    /// Paths skipped by `--exclude` in the last load
+    excluded_count: Arc<AtomicUsize>,
+    /// Set to whether the first GET request succeeded once it has been served
+    first_request: Arc<watch::Sender<Option<bool>>>,
+    watch_events: broadcast::Sender<WatchEvent>,
+    /// Components of the previous load, compared against to broadcast changes
+    snapshot: Snapshot,
+    /// Removals waiting for `--watch-coalesce-timeout` to pass
+    pending_removals: Arc<Mutex<PendingRemovals>>,
+    /// File each component was last loaded from, for `--watch-emit-events`
+    source_paths: Arc<Mutex<HashMap<ComponentKey, PathBuf>>>,
+    /// Notified after watch mode reloaded the components, streamed to `/events` subscribers
+    reload_events: broadcast::Sender<()>,
+    /// Base of the component URLs, set once the server knows the port it is bound to
+    base_url: Arc<OnceLock<String>>,
+    access_log: Option<AccessLog>,
+    metrics: Option<Metrics>,
+    /// Components of each account as of the last load, used with `--cache`
+    cache: CacheState,
+}
+
+impl RoutesOptions {
+    pub(crate) fn new(config: ResolvedConfig) -> Self {
+        Self {
+            accounts: Arc::new(RwLock::new(AccountRegistry::from_paths(
+                config.accounts.clone(),
+            ))),
+            replacements_map: Arc::new(watch::channel(config.replacements_map.clone()).0),
+            component_count: Arc::new(AtomicUsize::new(0)),
+            excluded_count: Arc::new(AtomicUsize::new(0)),
+            first_request: Arc::new(watch::channel(None).0),
+            watch_events: watch_channel().0,
+            snapshot: Snapshot::new(),
+            pending_removals: Arc::new(Mutex::new(PendingRemovals::default())),
+            source_paths: Arc::new(Mutex::new(HashMap::new())),
+            reload_events: broadcast::channel(WATCH_EVENT_CAPACITY).0,
+            base_url: Arc::new(OnceLock::new()),
+            access_log: config
+                .access_log
+                .clone()
+                .map(|path| AccessLog::new(path, config.access_log_max_size)),
+            metrics: config.metrics.then(Metrics::new),
+            cache: CacheState::new(),
+            config: Arc::new(config),
+        }
+    }
+
+    fn replacements_map(&self) -> Arc<ReplacementsMap> {
+        self.replacements_map.borrow().clone()
+    }
+
+    fn handle_request_options(
+        &self,
+        AccountPath {
+            path,
+            account,
+            prefix,
+        }: AccountPath,
+        report: Arc<Mutex<LoadReport>>,
+    ) -> HandleRequestOptions {
+        HandleRequestOptions {
+            path,
+            account,
+            prefix: prefix.unwrap_or_default(),
+            web_engine: self.config.web_engine,
+            key_format: self.config.key_format.clone(),
+            replacements_map: self.replacements_map(),
+            inject_repl_account: self.config.inject_repl_account,
+            hash_algorithm: self.config.hash_algorithm,
+            lint_rules: self.config.lint_rules.clone(),
+            lint_strict: self.config.lint_strict,
+            max_component_age: self.config.max_component_age,
+            max_nesting: self.config.max_nesting,
+            id_separator: self.config.id_separator.clone(),
+            strip_exports: self.config.strip_exports,
+            exclude: self.config.exclude.clone(),
+            include: self.config.include.clone(),
+            replace_in_keys: self.config.replace_in_keys,
+            max_file_size: self.config.max_file_size,
+            skip_oversized: self.config.skip_oversized,
+            report,
+        }
+    }
+
+    async fn load_account(
+        &self,
+        account_path: AccountPath,
+        report: Arc<Mutex<LoadReport>>,
+    ) -> Result<HashMap<ComponentKey, ComponentCode>, anyhow::Error> {
+        let AccountPath { path, account, .. } = account_path.clone();
+
+        if self.config.test_mode {
+            return load_test_mode_components(
+                &account,
+                &self.config.key_format,
+                &self.config.id_separator,
+                &self.replacements_map(),
+                self.config.inject_repl_account,
+            );
+        }
+
+        // unreadable trees are loaded uncached, reporting the error
+        let timestamps = if self.config.cache {
+            tree_timestamps(&path).await.ok()
+        } else {
+            None
+        };
+
+        if let Some(timestamps) = &timestamps {
+            if let Some((components, cached_report)) =
+                self.cache.get(&account_path, timestamps).await
+            {
+                report.lock().await.extend(&cached_report);
+
+                return Ok((*components).clone());
+            }
+        }
+
+        // a cached account is loaded into a report of its own, kept to replay on cache hits
+        let account_report = if timestamps.is_some() {
+            Arc::new(Mutex::new(LoadReport::default()))
+        } else {
+            report.clone()
+        };
+
+        let components = handle_request(
+            self.handle_request_options(account_path.clone(), account_report.clone()),
+        )
+        .await
+        .map_err(|err| {
+            anyhow!(
+                "Error handling request for account {}, path {:?} \n Error: {:?}",
+                account,
+                path,
+                err
+            )
+        })?;
+
+        let components = components.lock().await.clone();
+
+        if let Some(timestamps) = timestamps {
+            let account_report = account_report.lock().await.clone();
+            report.lock().await.extend(&account_report);
+
+            self.cache
+                .insert(
+                    account_path,
+                    timestamps,
+                    Arc::new(components.clone()),
+                    Arc::new(account_report),
+                )
+                .await;
+        }
+
+        Ok(components)
+    }
+
+    /// `--base-url`, or the address the server is bound to
+    fn base_url(&self) -> &str {
+        self.base_url.get_or_init(|| {
+            self.config
+                .base_url
+                .clone()
+                .unwrap_or_else(|| default_base_url(self.config.host, self.config.port))
+        })
+    }
+
+    /// Loads every account in its own task, logging the accounts that fail and only failing
+    /// when none could be loaded. Results are returned in account order.
+    async fn load_accounts_parallel(
+        &self,
+        accounts: &AccountRegistry,
+        report: Arc<Mutex<LoadReport>>,
+    ) -> Result<Vec<(AccountPath, HashMap<ComponentKey, ComponentCode>)>, anyhow::Error> {
+        let mut tasks = JoinSet::new();
+
+        for (index, account_path) in accounts.iter().cloned().enumerate() {
+            let options = self.clone();
+            let report = report.clone();
+
+            tasks.spawn(async move {
+                let components = options.load_account(account_path.clone(), report).await;
+
+                (index, account_path, components)
+            });
+        }
+
+        let mut loaded = Vec::new();
+        let mut errors = Vec::new();
+
+        while let Some(result) = tasks.join_next().await {
+            match result
+                .map_err(|err| anyhow!("Account loading task failed \n Error: {:?}", err))?
+            {
+                (index, account_path, Ok(components)) => {
+                    loaded.push((index, account_path, components))
+                }
+                (_, _, Err(err)) => errors.push(err.to_string()),
+            }
+        }
+
+        if !errors.is_empty() {
+            if loaded.is_empty() {
+                return Err(anyhow!(errors.join("\n")));
+            }
+
+            for error in &errors {
+                eprintln!("{error}");
+            }
+        }
+
+        loaded.sort_by_key(|(index, _, _)| *index);
+
+        Ok(loaded
+            .into_iter()
+            .map(|(_, account_path, components)| (account_path, components))
+            .collect())
+    }
+
+    /// Loads every account like [`Self::load_file_list`], broadcasting the changes since the
+    /// previous load, or the error, as watch events
+    async fn load_all(&self) -> Result<FileList, anyhow::Error> {
+        let result = self.load_file_list().await;
+
+        match &result {
+            Ok(file_list) => {
+                let (previous_version, previous) =
+                    self.snapshot.write(file_list.components.clone()).await;
+
+                // nothing changed before the first load
+                if previous_version > 0 {
+                    let events = diff_components(&previous, &file_list.components);
+                    let events = self.coalesce_removals(events).await;
+
+                    self.send_watch_events(events, &file_list.components).await;
+                }
+            }
+            Err(err) => {
+                let _ = self.watch_events.send(WatchEvent::Error(err.to_string()));
+            }
+        }
+
+        result
+    }
+
+    /// Sends the changes that led to `components`, appending them to `--watch-emit-events` and
+    /// logging them in watch mode
+    async fn send_watch_events(
+        &self,
+        events: Vec<WatchEvent>,
+        components: &HashMap<ComponentKey, ComponentCode>,
+    ) {
+        if let Some(events_path) = &self.config.watch_emit_events {
+            let mut source_paths = self.source_paths.lock().await;
+
+            if let Err(err) =
+                append_watch_events(events_path, &events, &source_paths, SystemTime::now()).await
+            {
+                eprintln!("Warning: could not write watch events: {}", err);
+            }
+
+            // held removals still need their paths
+            let pending_removals = self.pending_removals.lock().await;
+            source_paths
+                .retain(|key, _| components.contains_key(key) || pending_removals.contains(key));
+        }
+
+        for event in events {
+            if self.config.watch {
+                log_watch_event(&event, self.config.watch_event_log_level);
+            }
+
+            // an error only means nobody is subscribed
+            let _ = self.watch_events.send(event);
+        }
+    }
+
+    /// Holds the removals of a load for `--watch-coalesce-timeout`, sending them once it has passed
+    /// unless a component with the same key or file stem was added meanwhile
+    async fn coalesce_removals(&self, events: Vec<WatchEvent>) -> Vec<WatchEvent> {
+        let Some(timeout) = self.config.watch_coalesce_timeout else {
+            return events;
+        };
+
+        let holds_removals = events
+            .iter()
+            .any(|event| matches!(event, WatchEvent::Removed(_)));
+        let events = self.pending_removals.lock().await.coalesce(
+            events,
+            tokio::time::Instant::now(),
+            timeout,
+            |key| self.key_stem(key),
+        );
+
+        if holds_removals {
+            let options = self.clone();
+
+            tokio::spawn(async move {
+                tokio::time::sleep(timeout).await;
+
+                let removals = options
+                    .pending_removals
+                    .lock()
+                    .await
+                    .expired(tokio::time::Instant::now());
+                if !removals.is_empty() {
+                    let snapshot = options.snapshot.read().await;
+                    options
+                        .send_watch_events(removals, snapshot.components())
+                        .await;
+                }
+            });
+        }
+
+        events
+    }
+
+    /// The stem of the file `key` is loaded from, e.g. `Foo` for `alice.near/widget/ui.Foo`
+    fn key_stem(&self, key: &ComponentKey) -> Option<String> {
+        let resolver = ComponentPathResolver {
+            key_format: self.config.key_format.clone(),
+            ..ComponentPathResolver::new(
+                "",
+                key.account(),
+                self.config.web_engine,
+                &self.config.id_separator,
+            )
+        };
+
+        resolver
+            .resolve_reverse(key)?
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+    }
+
+    /// Drops the components of a removed account from the snapshot, sending a removal for each
+    /// instead of waiting for the next load
+    async fn remove_account_components(&self, account: &str) {
+        let mut components = {
+            let snapshot = self.snapshot.read().await;
+
+            // the first load won't have them
+            if snapshot.version() == 0 {
+                return;
+            }
+
+            snapshot.components().clone()
+        };
+        components.retain(|key, _| key.account() != account);
+
+        let (_, previous) = self.snapshot.write(components.clone()).await;
+        self.component_count
+            .store(components.len(), Ordering::Relaxed);
+        self.send_watch_events(diff_components(&previous, &components), &components)
+            .await;
+    }
+
+    /// Reloads every account after a change, notifying `/events` subscribers and running the
+    /// `--reload-script` when the load succeeds. Load errors are broadcast as watch events
+    async fn reload_changed(&self) {
+        let started = Instant::now();
+
+        if let Ok(file_list) = self.load_all().await {
+            // an error only means nobody is subscribed
+            let _ = self.reload_events.send(());
+
+            if let Some(script) = self.config.reload_script.clone() {
+                let component_count = file_list.components.len();
+                let duration = started.elapsed();
+
+                // the script must not hold up the next reload
+                tokio::spawn(async move {
+                    run_reload_script(&script, component_count, duration).await;
+                });
+            }
+        }
+    }
+
+    /// Re-reads the replacements after `--replacements-watch` saw them change and reloads every
+    /// component with the new values, keeping the previous ones when they can't be read
+    async fn reload_replacements(&self) {
+        let replacements_map = match self.config.replacements_sources.load().await {
+            Ok(replacements_map) => replacements_map,
+            Err(err) => {
+                eprintln!("Warning: could not reload the replacements: {}", err);
+                return;
+            }
+        };
+
+        tracing::info!(
+            "Replacements file changed, reloading {} components.",
+            self.component_count.load(Ordering::Relaxed)
+        );
+
+        self.replacements_map
+            .send_replace(Arc::new(replacements_map));
+        // the cached components have the previous values
+        self.cache.clear().await;
+        self.reload_changed().await;
+    }
+
+    /// Loads the components of every account, failing on the first account that can't be loaded
+    /// unless `parallel_accounts` is set
+    async fn load_file_list(&self) -> Result<FileList, anyhow::Error> {
+        let mut all_components = HashMap::new();
+        let accounts = self.accounts.read().await.clone();
+        let report = Arc::new(Mutex::new(LoadReport::default()));
+
+        let account_components = if self.config.parallel_accounts {
+            self.load_accounts_parallel(&accounts, report.clone())
+                .await?
+        } else {
+            let mut account_components = Vec::new();
+
+            for account_path in &accounts {
+                account_components.push((
+                    account_path.clone(),
+                    self.load_account(account_path.clone(), report.clone())
+                        .await?,
+                ));
+            }
+
+            account_components
+        };
+
+        let metadata = self
+            .config
+            .inject_account_metadata
+            .then(|| FileListMetadata {
+                accounts: account_components
+                    .iter()
+                    .map(
+                        |(AccountPath { path, account, .. }, components)| AccountMetadata {
+                            account: account.clone(),
+                            path: path.clone(),
+                            component_count: components.len(),
+                        },
+                    )
+                    .collect(),
+            });
+
+        for (_, components) in account_components {
+            all_components.extend(components);
+        }
+
+        if let (Some(tar_source), Some(account_path)) =
+            (&self.config.tar_source, accounts.iter().next())
+        {
+            // local components take priority over archived ones with the same key
+            let tar_components = load_components_from_tar_with_options(HandleRequestOptions {
+                path: tar_source.clone(),
+                ..self.handle_request_options(account_path.clone(), report.clone())
+            })
+            .await?;
+
+            for (key, code) in tar_components {
+                all_components.entry(key).or_insert(code);
+            }
+        }
+
+        if let Some(remote_source) = &self.config.remote_source {
+            // local components take priority over remote ones with the same key
+            for (key, code) in fetch_remote_components(remote_source).await? {
+                all_components.entry(key).or_insert(code);
+            }
+        }
+
+        if let Some(key_pattern) = &self.config.key_pattern {
+            let invalid_keys = keys_not_matching(&all_components, key_pattern);
+
+            if !invalid_keys.is_empty() {
+                let message = format!(
+                    "Component keys do not match pattern {}: {}",
+                    key_pattern,
+                    invalid_keys
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                );
+
+                if self.config.strict {
+                    return Err(anyhow!(message));
+                }
+
+                eprintln!("Warning: {}", message);
+            }
+        }
+
+        if let Some(size_budget) = &self.config.size_budget {
+            let violations = size_budget.violations(&all_components);
+
+            if !violations.is_empty() {
+                let message = format!(
+                    "Components are over the size budget: {}",
+                    violations.join(", ")
+                );
+
+                if self.config.strict {
+                    return Err(anyhow!(message));
+                }
+
+                eprintln!("Warning: {}", message);
+            }
+        }
+
+        if self.config.log_replacements_skipped {
+            let unused_keys = unused_replacement_keys(
+                &self.replacements_map(),
+                report
+                    .lock()
+                    .await
+                    .audit_log
+                    .iter()
+                    .map(|entry| &entry.substitution),
+            );
+
+            if !unused_keys.is_empty() {
+                eprintln!(
+                    "Warning: replacement keys not used by any component: {}",
+                    unused_keys.join(", ")
+                );
+            }
+        }
+
+        if self.config.watch_emit_events.is_some() {
+            self.source_paths.lock().await.extend(
+                report
+                    .lock()
+                    .await
+                    .stats
+                    .iter()
+                    .map(|stats| (stats.key.clone(), stats.source_path.clone())),
+            );
+        }
+
+        if let Some(stats_path) = &self.config.component_stats_file {
+            if let Err(err) = write_component_stats(stats_path, &report.lock().await.stats).await {
+                eprintln!("Warning: could not write component stats: {}", err);
+            }
+        }
+
+        if let Some(audit_log_path) = &self.config.replacements_audit_log {
+            if let Err(err) = write_audit_log(audit_log_path, &report.lock().await.audit_log).await
+            {
+                eprintln!("Warning: could not write replacements audit log: {}", err);
+            }
+        }
+
+        let entry_point = self.config.entry_point.as_ref().and_then(|name| {
+            let entry_point = find_entry_point(&all_components, name);
+
+            if entry_point.is_none() {
+                eprintln!("Warning: entry point component {} was not found", name);
+            }
+
+            entry_point
+        });
+
+        let base_url = self.base_url().trim_end_matches('/');
+        for (key, component) in all_components.iter_mut() {
+            component.url = Some(format!("{base_url}/components/{key}"));
+        }
+
+        self.component_count
+            .store(all_components.len(), Ordering::Relaxed);
+        self.excluded_count
+            .store(report.lock().await.excluded.len(), Ordering::Relaxed);
+
+        let accounts = self.config.serve_accounts_endpoint.then(|| {
+            let mut accounts = all_components
+                .keys()
+                .map(|key| key.account().to_string())
+                .collect::<Vec<String>>();
+            accounts.sort();
+            accounts.dedup();
+
+            accounts
+        });
+
+        Ok(FileList {
+            entry_point,
+            metadata,
+            accounts,
+            components: all_components,
+        })
+    }
+
+    /// Writes the first response to `--output-file` when configured and records its outcome
+    async fn first_request_served(&self, body: &serde_json::Value, success: bool) {
+        if !self.config.exit_after_first_request || self.first_request.borrow().is_some() {
+            return;
+        }
+
+        let mut success = success;
+
+        if let Some(output_file) = &self.config.output_file {
+            if let Err(err) = fs::write(output_file, body.to_string()).await {
+                eprintln!("Failed to write file {:?} \n Error: {:?}", output_file, err);
+                success = false;
+            }
+        }
+
+        self.first_request.send_if_modified(|outcome| {
+            if outcome.is_none() {
+                *outcome = Some(success);
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    /// Checks an `Authorization: Bearer <token>` header against `--reload-token`
+    fn authorize(&self, authorization: Option<String>) -> Result<(), (StatusCode, &'static str)> {
+        let Some(reload_token) = &self.config.reload_token else {
+            return Err((
+                StatusCode::FORBIDDEN,
+                "This endpoint is disabled, start bos-loader with --reload-token to enable it",
+            ));
+        };
+
+        match authorization {
+            Some(header) if header.strip_prefix("Bearer ") == Some(reload_token.as_str()) => Ok(()),
+            _ => Err((StatusCode::UNAUTHORIZED, "Invalid or missing bearer token")),
+        }
+    }
+}
+
+/// A strong `ETag` for a response body made of `chunks`, the quoted hex SHA-256 of its bytes
+fn body_etag<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> String {
+    use sha2::Digest;
+
+    let mut hasher = sha2::Sha256::new();
+    for chunk in chunks {
+        hasher.update(chunk);
+    }
+
+    format!(
+        "\"{}\"",
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>()
+    )
+}
+
+/// Whether an `If-None-Match` header lists `etag`, comparing weakly as GET requests do
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
+
+/// Emits one `tracing` event per request with its method, path, status and latency
+fn log_request(info: warp::log::Info) {
+    tracing::info!(
+        method = %info.method(),
+        path = info.path(),
+        status = info.status().as_u16(),
+        latency_ms = info.elapsed().as_secs_f64() * 1000.0,
+        "request"
+    );
+}
+
+pub(crate) fn routes(
+    options: RoutesOptions,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let access_log = options.access_log.clone();
+    let metrics_recorder = options.metrics.clone();
+    let cors = if options.config.cors_origins.is_empty() {
+        warp::cors().allow_any_origin()
+    } else {
+        warp::cors().allow_origins(options.config.cors_origins.iter().map(String::as_str))
+    };
+    // POST and DELETE, with the bearer token and a JSON body, are for the admin endpoints
+    let cors = cors
+        .allow_methods(&[Method::GET, Method::POST, Method::DELETE])
+        .allow_headers([AUTHORIZATION, CONTENT_TYPE])
+        .allow_headers(options.config.cors_allow_headers.clone());
+
+    let components_options = options.clone();
+    let components = warp::get()
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and_then(move |if_none_match: Option<String>| {
+            let options = components_options.clone();
+
+            async move {
+                let (body, lines, success) = match options.load_all().await {
+                    Ok(file_list) => {
+                        let lines = ndjson_lines(&file_list.components);
+
+                        (json!(file_list), lines, true)
+                    }
+                    Err(err) => {
+                        let error = err.to_string();
+
+                        println!("{error}");
+
+                        let body = json!({ "error": error });
+                        let lines = vec![body.to_string()];
+
+                        (body, lines, false)
+                    }
+                };
+
+                options.first_request_served(&body, success).await;
+
+                let (mut response, etag) = if options.config.output_ndjson {
+                    let lines = lines
+                        .into_iter()
+                        .map(|line| line + "\n")
+                        .collect::<Vec<String>>();
+                    let etag = body_etag(lines.iter().map(String::as_bytes));
+                    let stream = tokio_stream::iter(lines.into_iter().map(Ok::<_, Infallible>));
+
+                    let mut response = Response::new(warp::hyper::Body::wrap_stream(stream));
+                    response.headers_mut().insert(
+                        CONTENT_TYPE,
+                        HeaderValue::from_static("application/x-ndjson"),
+                    );
+
+                    (response, etag)
+                } else {
+                    let json = body.to_string();
+                    let etag = body_etag([json.as_bytes()]);
+
+                    let mut response = Response::new(json.into());
+                    response
+                        .headers_mut()
+                        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+                    (response, etag)
+                };
+
+                // errors aren't tagged, so a client never keeps revalidating a failed load
+                if success {
+                    if if_none_match
+                        .is_some_and(|if_none_match| etag_matches(&if_none_match, &etag))
+                    {
+                        response = Response::new(warp::hyper::Body::empty());
+                        *response.status_mut() = StatusCode::NOT_MODIFIED;
+                    }
+
+                    if let Ok(etag) = HeaderValue::from_str(&etag) {
+                        response.headers_mut().insert(ETAG, etag);
+                    }
+                }
+
+                response
+                    .headers_mut()
+                    .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+                Ok::<_, warp::Rejection>(response)
+            }
+        });
+
+    let component_list_options = options.clone();
+    let component_list = warp::get()
+        .and(warp::path("components"))
+        .and(warp::path::end())
+        .and_then(move || {
+            let options = component_list_options.clone();
+
+            async move {
+                let (body, status) = match options.load_all().await {
+                    Ok(file_list) => {
+                        let mut keys: Vec<ComponentKey> =
+                            file_list.components.into_keys().collect();
+                        keys.sort();
+
+                        (json!(keys), StatusCode::OK)
+                    }
+                    Err(err) => (
+                        json!({ "error": err.to_string() }),
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    ),
+                };
+
+                Ok::<_, warp::Rejection>(warp::reply::with_status(warp::reply::json(&body), status))
+            }
+        });
+
+    let component_options = options.clone();
+    let component = warp::get()
+        .and(warp::path("components"))
+        .and(warp::path::tail())
+        .and_then(move |tail: warp::path::Tail| {
+            let options = component_options.clone();
+
+            async move {
+                let (body, status) = match options.load_all().await {
+                    Ok(mut file_list) => match file_list.components.remove(tail.as_str()) {
+                        Some(component) => (json!(component), StatusCode::OK),
+                        None => (
+                            json!({ "error": format!("Component {} not found", tail.as_str()) }),
+                            StatusCode::NOT_FOUND,
+                        ),
+                    },
+                    Err(err) => (
+                        json!({ "error": err.to_string() }),
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    ),
+                };
+
+                Ok::<_, warp::Rejection>(warp::reply::with_status(warp::reply::json(&body), status))
+            }
+        });
+
+    let health = warp::get()
+        .and(warp::path("health"))
+        .and(warp::path::end())
+        .map(warp::reply);
+
+    // for load balancers and liveness probes, answers without touching the component directories
+    let healthz = warp::get()
+        .and(warp::path("healthz"))
+        .and(warp::path::end())
+        .map(|| warp::reply::json(&json!({ "status": "ok" })));
+
+    let metrics_options = options.metrics.clone();
+    let metrics = warp::get()
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .and_then(move || {
+            let metrics = metrics_options.clone();
+
+            async move {
+                let Some(metrics) = metrics else {
+                    return Err(warp::reject::not_found());
+                };
+
+                Ok(warp::reply::with_header(
+                    metrics.encode(),
+                    CONTENT_TYPE,
+                    "text/plain; version=0.0.4",
+                ))
+            }
+        });
+
+    let events_options = options.clone();
+    let events = warp::get()
+        .and(warp::path("events"))
+        .and(warp::path::end())
+        .and_then(move || {
+            let events_options = events_options.clone();
+
+            async move {
+                if !events_options.config.watch {
+                    return Err(warp::reject::not_found());
+                }
+
+                // a lagging subscriber missed some reloads, which still means it should reload
+                let stream = BroadcastStream::new(events_options.reload_events.subscribe())
+                    .map(|_| Ok::<_, Infallible>(warp::sse::Event::default().data("reload")));
+
+                Ok(warp::sse::reply(warp::sse::keep_alive().stream(stream)))
+            }
+        });
+
+    let add_account_options = options.clone();
+    let add_account = warp::path("accounts")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::content_length_limit(16 * 1024))
+        .and(warp::body::json())
+        .and_then(move |authorization: Option<String>, account_path: AccountPath| {
+            let options = add_account_options.clone();
+
+            async move {
+                if let Err((status, error)) = options.authorize(authorization) {
+                    return Ok::<_, warp::Rejection>(warp::reply::with_status(
+                        warp::reply::json(&json!({ "error": error })),
+                        status,
+                    ));
+                }
+
+                if let Err(err) = validate_account_id(&account_path.account) {
+                    return Ok(warp::reply::with_status(
+                        warp::reply::json(&json!({ "error": err.to_string() })),
+                        StatusCode::BAD_REQUEST,
+                    ));
+                }
+
+                match handle_request(options.handle_request_options(
+                    account_path.clone(),
+                    Arc::new(Mutex::new(LoadReport::default())),
+                ))
+                .await
+                {
+                    Ok(components) => {
+                        let component_count = components.lock().await.len();
+
+                        println!(
+                            "Added {:?} as account {}",
+                            account_path.path, account_path.account
+                        );
+
+                        let account = account_path.account.clone();
+                        options.accounts.write().await.add(account_path);
+
+                        Ok(warp::reply::with_status(
+                            warp::reply::json(&json!({
+                                "account": account,
+                                "components": component_count,
+                            })),
+                            StatusCode::CREATED,
+                        ))
+                    }
+                    Err(err) => Ok(warp::reply::with_status(
+                        warp::reply::json(&json!({
+                            "error": format!(
+                                "Error loading components for account {}, path {:?} \n Error: {:?}",
+                                account_path.account, account_path.path, err
+                            ),
+                        })),
+                        StatusCode::BAD_REQUEST,
+                    )),
+                }
+            }
+        });
+
+    let reload_options = options.clone();
+    let reload = warp::path("reload")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(move |authorization: Option<String>| {
+            let options = reload_options.clone();
+
+            async move {
+                // unlike the account endpoints, reloading is allowed without a token
+                if options.config.reload_token.is_some() {
+                    if let Err((status, error)) = options.authorize(authorization) {
+                        return Ok::<_, warp::Rejection>(warp::reply::with_status(
+                            warp::reply::json(&json!({ "error": error })),
+                            status,
+                        ));
+                    }
+                }
+
+                options.cache.clear().await;
+
+                match options.load_all().await {
+                    Ok(file_list) => {
+                        // an error only means nobody is subscribed
+                        let _ = options.reload_events.send(());
+
+                        Ok(warp::reply::with_status(
+                            warp::reply::json(&json!({ "reloaded": file_list.components.len() })),
+                            StatusCode::OK,
+                        ))
+                    }
+                    Err(err) => Ok(warp::reply::with_status(
+                        warp::reply::json(&json!({ "error": err.to_string() })),
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    )),
+                }
+            }
+        });
+
+    let remove_account_options = options;
+    let remove_account = warp::path!("accounts" / String)
+        .and(warp::delete())
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(move |account: String, authorization: Option<String>| {
+            let options = remove_account_options.clone();
+
+            async move {
+                if let Err((status, error)) = options.authorize(authorization) {
+                    return Ok::<_, warp::Rejection>(warp::reply::with_status(
+                        warp::reply::json(&json!({ "error": error })),
+                        status,
+                    ));
+                }
+
+                if options.accounts.write().await.remove(&account) {
+                    println!("Removed account {}", account);
+
+                    options.remove_account_components(&account).await;
+
+                    Ok(warp::reply::with_status(
+                        warp::reply::json(&json!({ "removed": account })),
+                        StatusCode::OK,
+                    ))
+                } else {
+                    Ok(warp::reply::with_status(
+                        warp::reply::json(&json!({
+                            "error": format!("Account {} is not being served", account),
+                        })),
+                        StatusCode::NOT_FOUND,
+                    ))
+                }
+            }
+        });
+
+    let routes = components
+        .or(component_list)
+        .or(component)
+        .or(health)
+        .or(healthz)
+        .or(events)
+        .or(metrics)
+        .or(add_account)
+        .or(remove_account)
+        .or(reload)
+        .with(cors)
+        .recover(recover_rejection)
+        .with(warp::reply::with::header(
+            "X-Content-Type-Options",
+            "nosniff",
+        ))
+        .with(warp::reply::with::header("X-Frame-Options", "DENY"))
+        .with(warp::log::custom(log_request));
+
+    with_access_log(with_metrics(routes, metrics_recorder), access_log)
+}
+
+/// Answers a rejection with its status, so the headers added to replies are set on 404s, 405s
+/// and other rejected requests too. The body is empty for a 404, like warp's own, and the reason
+/// phrase of the status otherwise
+async fn recover_rejection(rejection: warp::Rejection) -> Result<Response, warp::Rejection> {
+    let status = rejection_status(&rejection);
+    let body = if rejection.is_not_found() {
+        ""
+    } else {
+        status.canonical_reason().unwrap_or_default()
+    };
+
+    Ok(warp::Reply::into_response(warp::reply::with_status(
+        body, status,
+    )))
+}
+
+/// Controls a server started with [`run_server`]
+pub struct ServerHandle {
+    address: SocketAddr,
+    ipv6: bool,
+    shutdown: watch::Sender<bool>,
+    options: RoutesOptions,
+}
+
+impl ServerHandle {
+    /// The port the server is bound to, useful when started on port 0
+    pub fn port(&self) -> u16 {
+        self.address.port()
+    }
+
+    /// The address the server is bound to
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Whether the server is also bound to `[::1]:<port>`
+    pub fn serves_ipv6(&self) -> bool {
+        self.ipv6
+    }
+
+    /// Stops accepting connections, the server task finishes once in-flight requests complete
+    pub fn shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    /// Loads the components of every account, returning how many were loaded
+    pub async fn reload(&self) -> Result<usize, anyhow::Error> {
+        Ok(self.options.load_all().await?.components.len())
+    }
+
+    /// Waits until the first GET request has been served when `exit_after_first_request` is set,
+    /// returning whether it succeeded
+    pub async fn first_request(&self) -> bool {
+        let mut receiver = self.options.first_request.subscribe();
+
+        loop {
+            if let Some(success) = *receiver.borrow() {
+                return success;
+            }
+
+            if receiver.changed().await.is_err() {
+                return false;
+            }
+        }
+    }
+
+    /// The number of components served by the most recent load
+    pub fn component_count(&self) -> usize {
+        self.options.component_count.load(Ordering::Relaxed)
+    }
+
+    /// Receives the changes found by every load after the first one
+    pub fn subscribe_watch_events(&self) -> broadcast::Receiver<WatchEvent> {
+        self.options.watch_events.subscribe()
+    }
+
+    /// The components of the latest load
+    pub fn snapshot(&self) -> Snapshot {
+        self.options.snapshot.clone()
+    }
+}
+
+/// Binds a listener to an OS allocated port on `127.0.0.1`, returning the port with the listener
+/// so it stays reserved until the caller is done with it
+pub fn bind_random_port() -> std::io::Result<(u16, TcpListener)> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+
+    Ok((listener.local_addr()?.port(), listener))
+}
+
+/// `http://<host>:<port>`, using the loopback address when bound to all interfaces
+fn default_base_url(host: IpAddr, port: u16) -> String {
+    let host = if host.is_unspecified() {
+        IpAddr::V4(Ipv4Addr::LOCALHOST)
+    } else {
+        host
+    };
+
+    format!("http://{}", SocketAddr::new(host, port))
+}
+
+/// Resolves once `true` is sent on the shutdown channel or its sender is dropped
+async fn shutdown_signal(mut receiver: watch::Receiver<bool>) {
+    while !*receiver.borrow() {
+        if receiver.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct NdjsonLine<'a> {
+    key: &'a ComponentKey,
+    code: &'a str,
+}
+
+/// Serializes a component as a single `{"key", "code"}` JSON line, without the trailing newline
+pub fn ndjson_line(key: &ComponentKey, code: &str) -> String {
+    serde_json::to_string(&NdjsonLine { key, code }).unwrap_or_default()
+}
+
+/// The `--output-ndjson` lines of `components`, sorted by key
+pub(crate) fn ndjson_lines(components: &HashMap<ComponentKey, ComponentCode>) -> Vec<String> {
+    let mut components = components.iter().collect::<Vec<_>>();
+    components.sort_by_key(|(key, _)| *key);
+
+    components
+        .into_iter()
+        .map(|(key, component)| ndjson_line(key, &component.code))
+        .collect()
+}
+
+/// A component file found by [`dry_run`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DryRunFile {
+    pub key: ComponentKey,
+    pub source_path: PathBuf,
+    /// The code as loaded, without placeholders replaced
+    pub code: String,
+}
+
+/// Loads every account without replacing placeholders and returns each component file, sorted by
+/// key, to check which files would be served without starting a server
+pub async fn dry_run(config: ResolvedConfig) -> Result<Vec<DryRunFile>, anyhow::Error> {
+    let options = RoutesOptions::new(ResolvedConfig {
+        replacements_map: Arc::new(ReplacementsMap::default()),
+        inject_repl_account: false,
+        ..config
+    });
+    let report = Arc::new(Mutex::new(LoadReport::default()));
+    let mut components = HashMap::new();
+
+    for account_path in &options.config.accounts {
+        let AccountPath { path, account, .. } = account_path.clone();
+
+        let account_components =
+            handle_request(options.handle_request_options(account_path.clone(), report.clone()))
+                .await
+                .map_err(|err| {
+                    anyhow!(
+                        "Error loading account {}, path {:?} \n Error: {:?}",
+                        account,
+                        path,
+                        err
+                    )
+                })?;
+
+        components.extend(account_components.lock().await.drain());
+    }
+
+    let mut files = report
+        .lock()
+        .await
+        .stats
+        .iter()
+        .map(|stats| DryRunFile {
+            key: stats.key.clone(),
+            source_path: stats.source_path.clone(),
+            code: components
+                .get(&stats.key)
+                .map(|component| component.code.clone())
+                .unwrap_or_default(),
+        })
+        .collect::<Vec<DryRunFile>>();
+    files.sort_by(|a, b| (&a.key, &a.source_path).cmp(&(&b.key, &b.source_path)));
+
+    Ok(files)
+}
+
+/// Loads the components once, like a GET request would, and writes them to `output` as pretty
+/// printed JSON without starting a server. Component URLs are only kept with `--base-url`
+pub async fn export_components(
+    config: ResolvedConfig,
+    output: &Path,
+) -> Result<FileList, anyhow::Error> {
+    let keep_urls = config.base_url.is_some();
+    let mut file_list = RoutesOptions::new(config).load_file_list().await?;
+
+    if !keep_urls {
+        for component in file_list.components.values_mut() {
+            component.url = None;
+        }
+    }
+
+    let json = serde_json::to_string_pretty(&file_list)
+        .map_err(|err| anyhow!("Failed to serialize components \n Error: {:?}", err))?;
+    fs::write(output, json)
+        .await
+        .map_err(|err| anyhow!("Failed to write file {:?} \n Error: {:?}", output, err))?;
+
+    Ok(file_list)
+}
+
+/// Loads every account like a request to `/` would, running the checks of the config on the
+/// components without binding a port
+pub async fn check_components(config: ResolvedConfig) -> Result<FileList, anyhow::Error> {
+    RoutesOptions::new(config).load_file_list().await
+}
+
+/// Binds the server to `<config.host>:<config.port>`, and `[::1]:<port>` with `serve_on_ipv6`,
+/// and serves it on a spawned task
+pub async fn run_server(
+    config: ResolvedConfig,
+) -> Result<(ServerHandle, JoinHandle<()>), anyhow::Error> {
+    let host = config.host;
+    let port = config.port;
+    let serve_on_ipv6 = config.serve_on_ipv6;
+    let size_histogram_enabled = config.component_size_histogram;
+    let component_tree_enabled = config.component_tree;
+    let watch_enabled = config.watch;
+    let options = RoutesOptions::new(config);
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+
+    let ports = options.config.port_range.clone().unwrap_or(port..=port);
+    let mut bind_error = None;
+    let mut bound = None;
+
+    // the first free port of the range, every attempt needs its own shutdown signal
+    for port in ports.clone() {
+        match warp::serve(routes(options.clone())).try_bind_with_graceful_shutdown(
+            (host, port),
+            shutdown_signal(shutdown_receiver.clone()),
+        ) {
+            Ok(server) => {
+                bound = Some(server);
+                break;
+            }
+            Err(err) => bind_error = Some(err),
+        }
+    }
+
+    let (address, server) = bound.ok_or_else(|| match &options.config.port_range {
+        Some(range) => anyhow!(
+            "No free port in range {}-{} on {} \n Error: {:?}",
+            range.start(),
+            range.end(),
+            host,
+            bind_error
+        ),
+        None => anyhow!(
+            "Failed to bind to {}:{} \n Error: {:?}",
+            host,
+            port,
+            bind_error
+        ),
+    })?;
+
+    options.base_url.get_or_init(|| {
+        options
+            .config
+            .base_url
+            .clone()
+            .unwrap_or_else(|| default_base_url(host, address.port()))
+    });
+
+    let exclude_enabled = !options.config.exclude.is_empty();
+    // the budget is checked by every load, this reports it at startup
+    let size_budget_enabled = options.config.size_budget.is_some();
+
+    // watch mode diffs each reload against the previous load, the first change against this one
+    if size_histogram_enabled
+        || component_tree_enabled
+        || exclude_enabled
+        || size_budget_enabled
+        || watch_enabled
+    {
+        match options.load_all().await {
+            Ok(file_list) => {
+                if exclude_enabled {
+                    eprintln!(
+                        "Excluded {} files and directories matching --exclude",
+                        options.excluded_count.load(Ordering::Relaxed)
+                    );
+                }
+
+                if component_tree_enabled {
+                    eprint!(
+                        "Components:\n{}",
+                        format_component_tree(
+                            file_list.components.keys(),
+                            &options.config.id_separator
+                        )
+                    );
+                }
+
+                if size_histogram_enabled {
+                    eprint!(
+                        "Component sizes:\n{}",
+                        format_size_histogram(&size_histogram(&file_list.components))
+                    );
+                }
+            }
+            Err(err) => eprintln!(
+                "Warning: could not load components for the startup summary: {}",
+                err
+            ),
+        }
+    }
+
+    // bind IPv6 to the port IPv4 got, so both serve on the same port when started on port 0
+    let ipv6_server = if serve_on_ipv6 {
+        match warp::serve(routes(options.clone())).try_bind_with_graceful_shutdown(
+            (Ipv6Addr::LOCALHOST, address.port()),
+            shutdown_signal(shutdown_receiver),
+        ) {
+            Ok((_, ipv6_server)) => Some(ipv6_server),
+            Err(err) => {
+                eprintln!(
+                    "Warning: could not bind to [::1]:{}, serving on IPv4 only \n Error: {:?}",
+                    address.port(),
+                    err
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    if watch_enabled {
+        let paths = options
+            .accounts
+            .read()
+            .await
+            .iter()
+            .map(|account_path| account_path.path.clone())
+            .collect::<Vec<_>>();
+        let (watcher, mut changes) = watch_component_files(&paths)?;
+        let options = options.clone();
+        let shutdown_receiver = shutdown_sender.subscribe();
+
+        tokio::spawn(async move {
+            // dropping the watcher stops it
+            let _watcher = watcher;
+            let mut cooldown_end = None;
+
+            loop {
+                tokio::select! {
+                    changed = next_changes(&mut changes, cooldown_end) => {
+                        if !changed {
+                            break;
+                        }
+
+                        options.reload_changed().await;
+                        cooldown_end = options
+                            .config
+                            .watch_cooldown
+                            .map(|cooldown| tokio::time::Instant::now() + cooldown);
+                    }
+                    _ = shutdown_signal(shutdown_receiver.clone()) => break,
+                }
+            }
+        });
+    }
+
+    if watch_enabled && options.config.replacements_watch {
+        let mut changes = watch_files(
+            options
+                .config
+                .replacements_sources
+                .replacements_files
+                .clone(),
+        );
+        let options = options.clone();
+        let shutdown_receiver = shutdown_sender.subscribe();
+
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    change = changes.recv() => {
+                        if change.is_none() {
+                            break;
+                        }
+
+                        options.reload_replacements().await;
+                    }
+                    _ = shutdown_signal(shutdown_receiver.clone()) => break,
+                }
+            }
+        });
+    }
+
+    if let Some(trigger_file) = options.config.watch_trigger_file.clone() {
+        let mut triggers = watch_trigger_file(trigger_file);
+        let options = options.clone();
+        let shutdown_receiver = shutdown_sender.subscribe();
+
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    trigger = triggers.recv() => {
+                        if trigger.is_none() {
+                            break;
+                        }
+
+                        // a full reload like POST /reload, the cache might miss what the
+                        // filesystem events did
+                        options.cache.clear().await;
+                        options.reload_changed().await;
+                    }
+                    _ = shutdown_signal(shutdown_receiver.clone()) => break,
+                }
+            }
+        });
+    }
+
+    let ipv6 = ipv6_server.is_some();
+    let join_handle = tokio::spawn(async move {
+        match ipv6_server {
+            Some(ipv6_server) => {
+                tokio::join!(server, ipv6_server);
+            }
+            None => server.await,
+        }
+    });
+
+    Ok((
+        ServerHandle {
+            address,
+            ipv6,
+            shutdown: shutdown_sender,
+            options,
+        },
+        join_handle,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        budget::SizeBudget,
+        cli::ReplacementsSources,
+        components::{key_pattern, TEST_MODE_COMPONENTS},
+    };
+
+    fn test_routes() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        routes(RoutesOptions::new(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/webengine".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            reload_token: Some("secret".to_string()),
+            entry_point: Some("WithStyle".to_string()),
+            ..Default::default()
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_routes_serve_components() {
+        let response = warp::test::request()
+            .method("GET")
+            .path("/")
+            .reply(&test_routes())
+            .await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/json"
+        );
+
+        let body: FileList = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(
+            body.entry_point.as_deref(),
+            Some("test.near/widget/WithStyle")
+        );
+        assert_eq!(body.components.len(), 2);
+        assert!(body.components.contains_key("test.near/widget/WithStyle"));
+        assert!(body.components.contains_key("test.near/widget/NoStyle"));
+        assert_eq!(
+            body.components["test.near/widget/NoStyle"].url.as_deref(),
+            Some("http://127.0.0.1:3030/components/test.near/widget/NoStyle")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_routes_serve_accounts() {
+        let routes = routes(RoutesOptions::new(ResolvedConfig {
+            accounts: vec![
+                AccountPath {
+                    path: "./test/webengine".into(),
+                    account: "test.near".to_string(),
+                    prefix: None,
+                },
+                AccountPath {
+                    path: "./test/plain".into(),
+                    account: "alice.near".to_string(),
+                    prefix: None,
+                },
+                AccountPath {
+                    path: "./test/webengine".into(),
+                    account: "alice.near".to_string(),
+                    prefix: Some("Copy.".to_string()),
+                },
+            ],
+            serve_accounts_endpoint: true,
+            ..Default::default()
+        }));
+
+        let response = warp::test::request().path("/").reply(&routes).await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        assert_eq!(body["accounts"], json!(["alice.near", "test.near"]));
+        assert_eq!(body["components"].as_object().unwrap().len(), 6);
+
+        let response = warp::test::request().path("/").reply(&test_routes()).await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+
+        assert!(body.get("accounts").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_routes_etag() {
+        let routes = test_routes();
+
+        let response = warp::test::request().path("/").reply(&routes).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers().get("cache-control").unwrap(), "no-cache");
+
+        let etag = response.headers().get("etag").unwrap().clone();
+        assert_eq!(etag.len(), 66);
+
+        // unchanged components give the same tag
+        let response = warp::test::request()
+            .path("/")
+            .header("if-none-match", etag.clone())
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 304);
+        assert!(response.body().is_empty());
+        assert_eq!(response.headers().get("etag").unwrap(), &etag);
+
+        let response = warp::test::request()
+            .path("/")
+            .header(
+                "if-none-match",
+                format!("\"other\", W/{}", etag.to_str().unwrap()),
+            )
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 304);
+
+        let response = warp::test::request()
+            .path("/")
+            .header("if-none-match", "\"other\"")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert!(!response.body().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_routes_single_component() {
+        let routes = routes(RoutesOptions::new(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/webengine".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            base_url: Some("https://loader.example.com/".to_string()),
+            ..Default::default()
+        }));
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/components/test.near/widget/NoStyle")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), 200);
+        let component: ComponentCode = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            component.url.as_deref(),
+            Some("https://loader.example.com/components/test.near/widget/NoStyle")
+        );
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/components/test.near/widget/Missing")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_routes_component_list() {
+        let response = warp::test::request()
+            .method("GET")
+            .path("/components")
+            .reply(&test_routes())
+            .await;
+
+        assert_eq!(response.status(), 200);
+        let keys: Vec<String> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            keys,
+            vec!["test.near/widget/NoStyle", "test.near/widget/WithStyle"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_routes_output_ndjson() {
+        let routes = routes(RoutesOptions::new(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/webengine".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            output_ndjson: true,
+            ..Default::default()
+        }));
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/x-ndjson"
+        );
+
+        let body = std::str::from_utf8(response.body()).unwrap();
+        assert!(body.ends_with('\n'));
+
+        let lines = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect::<Vec<serde_json::Value>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["key"], "test.near/widget/NoStyle");
+        assert_eq!(lines[1]["key"], "test.near/widget/WithStyle");
+        assert!(lines[1]["code"].as_str().unwrap().contains("return"));
+    }
+
+    #[tokio::test]
+    async fn test_routes_test_mode() {
+        let routes = routes(RoutesOptions::new(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./does-not-exist".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            test_mode: true,
+            ..Default::default()
+        }));
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), 200);
+        let body: FileList = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body.components.len(), TEST_MODE_COMPONENTS.len());
+        assert_eq!(
+            body.components["test.near/widget/HelloWorld"].code,
+            "return <>Hello World</>;"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_routes_cache() {
+        let path = std::env::temp_dir().join("bos-loader-test-cache");
+        let file = path.join("A.jsx");
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        std::fs::write(&file, "return <>A</>;").unwrap();
+        let modified = std::fs::metadata(&file).unwrap().modified().unwrap();
+
+        let routes = routes(RoutesOptions::new(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: path.clone(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            cache: true,
+            ..Default::default()
+        }));
+        let code = || async {
+            let response = warp::test::request().path("/").reply(&routes).await;
+            let body: FileList = serde_json::from_slice(response.body()).unwrap();
+
+            body.components["test.near/widget/A"].code.clone()
+        };
+
+        assert_eq!(code().await, "return <>A</>;");
+
+        // same timestamps, the cached component is served
+        std::fs::write(&file, "return <>B</>;").unwrap();
+        let set_modified = |time| {
+            std::fs::File::options()
+                .write(true)
+                .open(&file)
+                .unwrap()
+                .set_modified(time)
+                .unwrap()
+        };
+        set_modified(modified);
+        assert_eq!(code().await, "return <>A</>;");
+
+        set_modified(modified + std::time::Duration::from_secs(60));
+        assert_eq!(code().await, "return <>B</>;");
+    }
+
+    #[tokio::test]
+    async fn test_cache_replays_load_report() {
+        let path = std::env::temp_dir().join("bos-loader-test-cache-report");
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(path.join("src")).unwrap();
+        std::fs::write(path.join("src/A.jsx"), "return <>${REPL_NAME}</>;").unwrap();
+        let audit_log = path.join("audit.log");
+        let stats_file = path.join("stats.csv");
+
+        let mut replacements_map = ReplacementsMap::default();
+        replacements_map
+            .insert("REPL_NAME".to_string(), "a".to_string())
+            .unwrap();
+        let options = RoutesOptions::new(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: path.join("src"),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            cache: true,
+            replacements_map: Arc::new(replacements_map),
+            replacements_audit_log: Some(audit_log.clone()),
+            component_stats_file: Some(stats_file.clone()),
+            watch_emit_events: Some(path.join("events.log")),
+            ..Default::default()
+        });
+
+        let key = ComponentKey::parse("test.near/widget/A").unwrap();
+        let mut reports = Vec::new();
+        for _ in 0..2 {
+            options.source_paths.lock().await.clear();
+            options.load_all().await.unwrap();
+
+            assert_eq!(
+                options.source_paths.lock().await.get(&key),
+                Some(&path.join("src/A.jsx"))
+            );
+            reports.push((
+                std::fs::read_to_string(&audit_log).unwrap(),
+                std::fs::read_to_string(&stats_file).unwrap(),
+            ));
+        }
+
+        // the second load is served from the cache with the report of the first
+        assert!(reports[0].0.contains("REPL_NAME"), "{}", reports[0].0);
+        assert!(
+            reports[0].1.contains("test.near/widget/A"),
+            "{}",
+            reports[0].1
+        );
+        assert_eq!(reports[0], reports[1]);
+    }
+
+    #[tokio::test]
+    async fn test_routes_reload() {
+        let path = std::env::temp_dir().join("bos-loader-test-reload");
+        let file = path.join("A.jsx");
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        std::fs::write(&file, "return <>A</>;").unwrap();
+        let modified = std::fs::metadata(&file).unwrap().modified().unwrap();
+
+        let routes = routes(RoutesOptions::new(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: path.clone(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            cache: true,
+            reload_token: Some("secret".to_string()),
+            ..Default::default()
+        }));
+        let code = || async {
+            let response = warp::test::request().path("/").reply(&routes).await;
+            let body: FileList = serde_json::from_slice(response.body()).unwrap();
+
+            body.components["test.near/widget/A"].code.clone()
+        };
+
+        assert_eq!(code().await, "return <>A</>;");
+
+        // an edit the cache can't see, keeping the modification time
+        std::fs::write(&file, "return <>B</>;").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert_eq!(code().await, "return <>A</>;");
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/reload")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 401);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/reload")
+            .header("authorization", "Bearer secret")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(response.body()).unwrap(),
+            json!({ "reloaded": 1 })
+        );
+        assert_eq!(code().await, "return <>B</>;");
+
+        // without a token the endpoint is open
+        let response = warp::test::request()
+            .method("POST")
+            .path("/reload")
+            .reply(&super::routes(RoutesOptions::new(ResolvedConfig {
+                accounts: vec![AccountPath {
+                    path: "./test/webengine".into(),
+                    account: "test.near".to_string(),
+                    prefix: None,
+                }],
+                ..Default::default()
+            })))
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(response.body()).unwrap(),
+            json!({ "reloaded": 2 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_routes_cors_preflight() {
+        let response = warp::test::request()
+            .method("OPTIONS")
+            .path("/")
+            .header("origin", "https://near.org")
+            .header("access-control-request-method", "GET")
+            .reply(&test_routes())
+            .await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response
+                .headers()
+                .get("access-control-allow-origin")
+                .unwrap(),
+            "https://near.org"
+        );
+        assert!(response
+            .headers()
+            .get("access-control-allow-methods")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .contains("GET"));
+    }
+
+    #[tokio::test]
+    async fn test_routes_cors_preflight_admin_endpoints() {
+        for (method, path) in [("POST", "/reload"), ("DELETE", "/accounts/bob.near")] {
+            let response = warp::test::request()
+                .method("OPTIONS")
+                .path(path)
+                .header("origin", "https://near.org")
+                .header("access-control-request-method", method)
+                .header(
+                    "access-control-request-headers",
+                    "authorization,content-type",
+                )
+                .reply(&test_routes())
+                .await;
+
+            assert_eq!(response.status(), 200, "{method} {path}");
+            let header = |name| {
+                response
+                    .headers()
+                    .get(name)
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .to_string()
+            };
+            assert!(header("access-control-allow-methods").contains(method));
+            assert!(header("access-control-allow-headers").contains("authorization"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_routes_cors_origins() {
+        let routes = routes(RoutesOptions::new(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/webengine".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            cors_origins: vec!["https://near.org".to_string()],
+            ..Default::default()
+        }));
+
+        let allowed = warp::test::request()
+            .method("GET")
+            .path("/")
+            .header("origin", "https://near.org")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(allowed.status(), 200);
+        assert_eq!(
+            allowed
+                .headers()
+                .get("access-control-allow-origin")
+                .unwrap(),
+            "https://near.org"
+        );
+
+        let denied = warp::test::request()
+            .method("GET")
+            .path("/")
+            .header("origin", "https://evil.example")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(denied.status(), 403);
+        assert!(denied
+            .headers()
+            .get("access-control-allow-origin")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_routes_cors_allow_headers() {
+        let routes = routes(RoutesOptions::new(ResolvedConfig {
+            cors_allow_headers: vec![warp::http::HeaderName::from_static("x-request-id")],
+            ..Default::default()
+        }));
+
+        let response = warp::test::request()
+            .method("OPTIONS")
+            .path("/")
+            .header("origin", "https://near.org")
+            .header("access-control-request-method", "GET")
+            .header("access-control-request-headers", "x-request-id")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), 200);
+        let allow_headers = response
+            .headers()
+            .get("access-control-allow-headers")
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(allow_headers.contains("x-request-id"));
+        assert!(allow_headers.contains("authorization"));
+        assert!(allow_headers.contains("content-type"));
+
+        let response = warp::test::request()
+            .method("OPTIONS")
+            .path("/")
+            .header("origin", "https://near.org")
+            .header("access-control-request-method", "GET")
+            .header("access-control-request-headers", "x-custom")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), 403);
+    }
+
+    #[tokio::test]
+    async fn test_routes_security_headers() {
+        let response = warp::test::request()
+            .method("GET")
+            .path("/health")
+            .reply(&test_routes())
+            .await;
+
+        assert_eq!(
+            response.headers().get("x-content-type-options").unwrap(),
+            "nosniff"
+        );
+        assert_eq!(response.headers().get("x-frame-options").unwrap(), "DENY");
+
+        for (method, path, status) in [("GET", "/unknown", 404), ("PUT", "/health", 405)] {
+            let response = warp::test::request()
+                .method(method)
+                .path(path)
+                .reply(&test_routes())
+                .await;
+
+            assert_eq!(response.status(), status, "{method} {path}");
+            assert_eq!(
+                response.headers().get("x-content-type-options").unwrap(),
+                "nosniff"
+            );
+            assert_eq!(response.headers().get("x-frame-options").unwrap(), "DENY");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_routes_unknown_route() {
+        let response = warp::test::request()
+            .method("GET")
+            .path("/unknown")
+            .reply(&test_routes())
+            .await;
+
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_routes_access_log() {
+        let path = std::env::temp_dir().join("bos-loader-test-routes-access.log");
+        let _ = std::fs::remove_file(&path);
+        let routes = routes(RoutesOptions::new(ResolvedConfig {
+            access_log: Some(path.clone()),
+            ..Default::default()
+        }));
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/health")
+            .header("user-agent", "curl/8.0")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/unknown")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 404);
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("\"GET /health HTTP/1.1\" 200 "));
+        assert!(lines[0].ends_with("\"-\" \"curl/8.0\""));
+        assert!(lines[1].contains("\"GET /unknown HTTP/1.1\" 404 - "));
+    }
+
+    #[tokio::test]
+    async fn test_routes_health() {
+        let response = warp::test::request()
+            .method("GET")
+            .path("/health")
+            .reply(&test_routes())
+            .await;
+
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_routes_healthz() {
+        let routes = routes(RoutesOptions::new(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./does-not-exist".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            ..Default::default()
+        }));
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/healthz")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body().as_ref(), br#"{"status":"ok"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_routes_request_log() {
+        #[derive(Clone, Default)]
+        struct Output(Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Output {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let output = Output::default();
+        let writer = output.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .flatten_event(true)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        warp::test::request()
+            .method("GET")
+            .path("/health")
+            .reply(&test_routes())
+            .await;
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let line: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+
+        assert_eq!(line["message"], "request");
+        assert_eq!(line["method"], "GET");
+        assert_eq!(line["path"], "/health");
+        assert_eq!(line["status"], 200);
+        assert!(line["latency_ms"].is_f64());
+        assert!(line["timestamp"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_routes_manage_accounts() {
+        let routes = test_routes();
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/accounts")
+            .json(&json!({ "account": "bob.near", "path": "./test/components" }))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 401);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/accounts")
+            .header("authorization", "Bearer secret")
+            .json(&json!({ "account": "Bob.near", "path": "./test/components" }))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 400);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/accounts")
+            .header("authorization", "Bearer secret")
+            .json(&json!({ "account": "bob.near", "path": "./test/components" }))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 201);
+
+        let response = warp::test::request().path("/").reply(&routes).await;
+        let body: FileList = serde_json::from_slice(response.body()).unwrap();
+        assert!(body.components.contains_key("bob.near/widget/Posts"));
+
+        let response = warp::test::request()
+            .method("DELETE")
+            .path("/accounts/bob.near")
+            .header("authorization", "Bearer secret")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+
+        let response = warp::test::request().path("/").reply(&routes).await;
+        let body: FileList = serde_json::from_slice(response.body()).unwrap();
+        assert!(!body.components.contains_key("bob.near/widget/Posts"));
+
+        let response = warp::test::request()
+            .method("DELETE")
+            .path("/accounts/bob.near")
+            .header("authorization", "Bearer secret")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_routes_remove_account_components() {
+        let options = RoutesOptions::new(ResolvedConfig {
+            accounts: vec![
+                AccountPath {
+                    path: "./test/webengine".into(),
+                    account: "test.near".to_string(),
+                    prefix: None,
+                },
+                AccountPath {
+                    path: "./test/components/Sub".into(),
+                    account: "bob.near".to_string(),
+                    prefix: None,
+                },
+            ],
+            reload_token: Some("secret".to_string()),
+            ..Default::default()
+        });
+        let routes = routes(options.clone());
+        let key = ComponentKey::parse("bob.near/widget/a").unwrap();
+
+        let response = warp::test::request().path("/").reply(&routes).await;
+        let body: FileList = serde_json::from_slice(response.body()).unwrap();
+        assert!(body.components.contains_key(&key));
+        let mut events = options.watch_events.subscribe();
+
+        let response = warp::test::request()
+            .method("DELETE")
+            .path("/accounts/bob.near")
+            .header("authorization", "Bearer secret")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+
+        // gone from the snapshot and announced before the next load
+        assert_eq!(events.try_recv().unwrap(), WatchEvent::Removed(key.clone()));
+        assert!(events.try_recv().is_err());
+        let snapshot = options.snapshot.read().await;
+        assert!(!snapshot.components().contains_key(&key));
+        assert!(snapshot
+            .components()
+            .keys()
+            .all(|key| key.account() == "test.near"));
+        drop(snapshot);
+
+        let response = warp::test::request().path("/").reply(&routes).await;
+        let body: FileList = serde_json::from_slice(response.body()).unwrap();
+        assert!(!body.components.contains_key(&key));
+        assert!(!body.components.is_empty());
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        let files = dry_run(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/components/Sub2".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            files
+                .iter()
+                .map(|file| (file.key.as_str(), file.source_path.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "test.near/widget/Sub2a.c",
+                    PathBuf::from("./test/components/Sub2/Sub2a/c.jsx")
+                ),
+                (
+                    "test.near/widget/b",
+                    PathBuf::from("./test/components/Sub2/b.jsx")
+                ),
+            ]
+        );
+        assert_eq!(
+            files[1].code,
+            std::fs::read_to_string("./test/components/Sub2/b.jsx").unwrap()
+        );
+
+        assert!(dry_run(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./does-not-exist".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            ..Default::default()
+        })
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_export_components() {
+        let output = std::env::temp_dir().join("bos-loader-test-export.json");
+        let _ = std::fs::remove_file(&output);
+
+        let file_list = export_components(
+            ResolvedConfig {
+                accounts: vec![AccountPath {
+                    path: "./test/webengine".into(),
+                    account: "test.near".to_string(),
+                    prefix: None,
+                }],
+                ..Default::default()
+            },
+            &output,
+        )
+        .await
+        .unwrap();
+
+        let contents = std::fs::read_to_string(&output).unwrap();
+        assert!(contents.starts_with("{\n  \"components\": {"));
+
+        let written: FileList = serde_json::from_str(&contents).unwrap();
+        assert_eq!(written.components, file_list.components);
+        assert_eq!(written.components.len(), 2);
+        assert!(written.components["test.near/widget/NoStyle"].url.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_run_server() {
+        let (server, join_handle) = run_server(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/webengine".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            port: 0,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        assert_ne!(server.port(), 0);
+        assert_eq!(server.component_count(), 0);
+        assert_eq!(server.reload().await.unwrap(), 2);
+        assert_eq!(server.component_count(), 2);
+        assert_eq!(server.snapshot().read().await.version(), 1);
+
+        server.shutdown();
+        join_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_server_port_range() {
+        let (taken, _listener) = bind_random_port().unwrap();
+        let config = |port_range| ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/webengine".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            port_range: Some(port_range),
+            ..Default::default()
+        };
+
+        let (server, join_handle) = run_server(config(taken..=taken.saturating_add(20)))
+            .await
+            .unwrap();
+
+        assert!(server.port() > taken && server.port() <= taken.saturating_add(20));
+
+        server.shutdown();
+        join_handle.await.unwrap();
+
+        let Err(err) = run_server(config(taken..=taken)).await else {
+            panic!("bound to the taken port {taken}");
+        };
+        assert!(err
+            .to_string()
+            .starts_with(&format!("No free port in range {taken}-{taken}")));
+    }
+
+    #[tokio::test]
+    async fn test_run_server_all_interfaces() {
+        let (server, join_handle) = run_server(ResolvedConfig {
+            host: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 0,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        assert!(server.address().ip().is_unspecified());
+        let response = reqwest::get(format!("http://127.0.0.1:{}/health", server.port()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            default_base_url(server.address().ip(), 3030),
+            "http://127.0.0.1:3030"
+        );
+        assert_eq!(
+            default_base_url(IpAddr::V6(Ipv6Addr::LOCALHOST), 3030),
+            "http://[::1]:3030"
+        );
+
+        server.shutdown();
+        join_handle.await.unwrap();
+    }
+
+    #[test]
+    fn test_bind_random_port() {
+        let (port, listener) = bind_random_port().unwrap();
+
+        assert_ne!(port, 0);
+        assert_eq!(listener.local_addr().unwrap().port(), port);
+        assert!(TcpListener::bind((Ipv4Addr::LOCALHOST, port)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_watch_events() {
+        let options = RoutesOptions::new(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/webengine".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            ..Default::default()
+        });
+        let mut events = options.watch_events.subscribe();
+
+        options.load_all().await.unwrap();
+        options.accounts.write().await.add(AccountPath {
+            path: "./test/components/Sub2".into(),
+            account: "sub.near".to_string(),
+            prefix: None,
+        });
+        options.load_all().await.unwrap();
+
+        assert!(
+            matches!(events.try_recv(), Ok(WatchEvent::Added(key)) if key.account() == "sub.near")
+        );
+
+        options.accounts.write().await.add(AccountPath {
+            path: "./test/missing".into(),
+            account: "missing.near".to_string(),
+            prefix: None,
+        });
+        options.load_all().await.unwrap_err();
+
+        let mut last = None;
+        while let Ok(event) = events.try_recv() {
+            last = Some(event);
+        }
+        assert!(matches!(last, Some(WatchEvent::Error(_))));
+    }
+
+    #[tokio::test]
+    async fn test_routes_events_require_watch() {
+        let response = warp::test::request()
+            .method("GET")
+            .path("/events")
+            .reply(&test_routes())
+            .await;
+
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_routes_metrics() {
+        let response = warp::test::request()
+            .method("GET")
+            .path("/metrics")
+            .reply(&test_routes())
+            .await;
+        assert_eq!(response.status(), 404);
+
+        let routes = super::routes(RoutesOptions::new(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/webengine".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            metrics: true,
+            ..Default::default()
+        }));
+
+        for path in ["/", "/components", "/missing"] {
+            warp::test::request().path(path).reply(&routes).await;
+        }
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/metrics")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers()[CONTENT_TYPE],
+            "text/plain; version=0.0.4"
+        );
+
+        // the scrape itself is counted once it has been answered
+        let body = String::from_utf8_lossy(response.body());
+        assert!(body.contains("bos_loader_requests_total 3\n"), "{body}");
+        assert!(body.contains("bos_loader_errors_total 1\n"), "{body}");
+        assert!(body.contains("bos_loader_response_latency_seconds_bucket{le=\"+Inf\"} 3\n"));
+    }
+
+    #[tokio::test]
+    async fn test_watch_reload_events() {
+        let path = std::env::temp_dir().join("bos-loader-test-watch");
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        std::fs::write(path.join("Watched.jsx"), "return <>before</>;").unwrap();
+        let events_path = std::env::temp_dir().join("bos-loader-test-watch-events.log");
+        let _ = std::fs::remove_file(&events_path);
+
+        let (server, join_handle) = run_server(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: path.clone(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            port: 0,
+            watch: true,
+            watch_emit_events: Some(events_path.clone()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let mut response = reqwest::get(format!("http://127.0.0.1:{}/events", server.port()))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "text/event-stream"
+        );
+
+        std::fs::write(path.join("Watched.jsx"), "return <>after</>;").unwrap();
+
+        let mut received = String::new();
+        tokio::time::timeout(std::time::Duration::from_secs(10), async {
+            while !received.contains("data:reload") {
+                let chunk = response.chunk().await.unwrap().unwrap();
+                received.push_str(&String::from_utf8_lossy(&chunk));
+            }
+        })
+        .await
+        .unwrap();
+
+        let events = std::fs::read_to_string(&events_path).unwrap();
+        assert!(
+            events.contains("\"event_type\":\"modify\",\"key\":\"test.near/widget/Watched\""),
+            "{events}"
+        );
+        assert!(events.contains("Watched.jsx\"}\n"), "{events}");
+
+        drop(response);
+        server.shutdown();
+        join_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_watch_coalesce_timeout() {
+        let path = std::env::temp_dir().join("bos-loader-test-watch-coalesce");
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(path.join("old")).unwrap();
+        std::fs::write(path.join("old/Moved.jsx"), "return <>moved</>;").unwrap();
+        std::fs::write(path.join("Gone.jsx"), "return <>gone</>;").unwrap();
+        let events_path = path.with_extension("log");
+        let _ = std::fs::remove_file(&events_path);
+
+        let options = RoutesOptions::new(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: path.clone(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            watch: true,
+            watch_coalesce_timeout: Some(std::time::Duration::from_millis(300)),
+            watch_emit_events: Some(events_path.clone()),
+            ..Default::default()
+        });
+        options.load_all().await.unwrap();
+        let mut events = options.watch_events.subscribe();
+
+        // a move seen by two loads, the removal first
+        std::fs::remove_dir_all(path.join("old")).unwrap();
+        std::fs::remove_file(path.join("Gone.jsx")).unwrap();
+        options.load_all().await.unwrap();
+        assert!(events.try_recv().is_err());
+
+        std::fs::create_dir_all(path.join("new")).unwrap();
+        std::fs::write(path.join("new/Moved.jsx"), "return <>moved</>;").unwrap();
+        options.load_all().await.unwrap();
+        assert_eq!(
+            events.try_recv().unwrap(),
+            WatchEvent::Modified(ComponentKey::parse("test.near/widget/new.Moved").unwrap())
+        );
+
+        // nothing takes the other removal back
+        let event = tokio::time::timeout(std::time::Duration::from_secs(5), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            event,
+            WatchEvent::Removed(ComponentKey::parse("test.near/widget/Gone").unwrap())
+        );
+        assert!(events.try_recv().is_err());
+
+        let emitted = std::fs::read_to_string(&events_path).unwrap();
+        assert_eq!(emitted.lines().count(), 2, "{emitted}");
+        assert!(
+            emitted.contains("\"event_type\":\"remove\",\"key\":\"test.near/widget/Gone\""),
+            "{emitted}"
+        );
+        assert!(emitted.contains("Gone.jsx\"}"), "{emitted}");
+    }
+
+    #[tokio::test]
+    async fn test_watch_trigger_file() {
+        let path = std::env::temp_dir().join("bos-loader-test-watch-trigger-file");
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        std::fs::write(path.join("Triggered.jsx"), "return <>before</>;").unwrap();
+        let trigger_file = path.join("reload");
+
+        let (server, join_handle) = run_server(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: path.clone(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            port: 0,
+            watch_trigger_file: Some(trigger_file.clone()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let mut events = server.subscribe_watch_events();
+
+        let url = format!("http://127.0.0.1:{}", server.port());
+        reqwest::get(&url).await.unwrap();
+
+        // without --watch the change is only picked up once the trigger file is touched
+        std::fs::write(path.join("Triggered.jsx"), "return <>after</>;").unwrap();
+        std::fs::write(&trigger_file, "").unwrap();
+
+        let event = tokio::time::timeout(std::time::Duration::from_secs(10), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            event,
+            WatchEvent::Modified(ComponentKey::parse("test.near/widget/Triggered").unwrap())
+        );
+
+        server.shutdown();
+        join_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replacements_watch() {
+        let path = std::env::temp_dir().join("bos-loader-test-replacements-watch");
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(path.join("src")).unwrap();
+        std::fs::write(
+            path.join("src/Greeting.jsx"),
+            "return <>${REPL_GREETING}</>;",
+        )
+        .unwrap();
+        let replacements_file = path.join("replacements.json");
+        std::fs::write(&replacements_file, r#"{"REPL_GREETING": "before"}"#).unwrap();
+
+        let replacements_sources = ReplacementsSources {
+            replacements_files: vec![replacements_file.clone()],
+            ..Default::default()
+        };
+        let (server, join_handle) = run_server(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: path.join("src"),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            port: 0,
+            watch: true,
+            replacements_watch: true,
+            replacements_map: Arc::new(replacements_sources.load().await.unwrap()),
+            replacements_sources,
+            cache: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let mut events = server.subscribe_watch_events();
+
+        std::fs::write(&replacements_file, r#"{"REPL_GREETING": "after"}"#).unwrap();
+
+        let event = tokio::time::timeout(std::time::Duration::from_secs(10), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            event,
+            WatchEvent::Modified(ComponentKey::parse("test.near/widget/Greeting").unwrap())
+        );
+        let key = ComponentKey::parse("test.near/widget/Greeting").unwrap();
+        assert_eq!(
+            server.snapshot().read().await.components()[&key].code,
+            "return <>after</>;"
+        );
+
+        server.shutdown();
+        join_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_server_ipv6() {
+        let (server, join_handle) = run_server(ResolvedConfig {
+            port: 0,
+            serve_on_ipv6: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        tokio::net::TcpStream::connect(("127.0.0.1", server.port()))
+            .await
+            .unwrap();
+
+        // IPv6 might not be available in the test environment
+        if server.serves_ipv6() {
+            tokio::net::TcpStream::connect(("::1", server.port()))
+                .await
+                .unwrap();
+        }
+
+        server.shutdown();
+        join_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_parallel_accounts() {
+        let config = ResolvedConfig {
+            accounts: vec![
+                AccountPath {
+                    path: "./test/webengine".into(),
+                    account: "test.near".to_string(),
+                    prefix: None,
+                },
+                AccountPath {
+                    path: "./test/missing".into(),
+                    account: "missing.near".to_string(),
+                    prefix: None,
+                },
+            ],
+            ..Default::default()
+        };
+
+        assert!(RoutesOptions::new(config.clone()).load_all().await.is_err());
+
+        let file_list = RoutesOptions::new(ResolvedConfig {
+            parallel_accounts: true,
+            ..config
+        })
+        .load_all()
+        .await
+        .unwrap();
+        assert_eq!(file_list.components.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_inject_account_metadata() {
+        let config = ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/webengine".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            ..Default::default()
+        };
+
+        let file_list = RoutesOptions::new(config.clone()).load_all().await.unwrap();
+        assert_eq!(file_list.metadata, None);
+
+        let file_list = RoutesOptions::new(ResolvedConfig {
+            inject_account_metadata: true,
+            ..config
+        })
+        .load_all()
+        .await
+        .unwrap();
+        assert_eq!(
+            file_list.metadata,
+            Some(FileListMetadata {
+                accounts: vec![AccountMetadata {
+                    account: "test.near".to_string(),
+                    path: "./test/webengine".into(),
+                    component_count: 2,
+                }]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remote_source() {
+        let (remote, join_handle) = run_server(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/webengine".into(),
+                account: "remote.near".to_string(),
+                prefix: None,
+            }],
+            port: 0,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let file_list = RoutesOptions::new(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/webengine".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            remote_source: Some(format!("http://127.0.0.1:{}", remote.port())),
+            ..Default::default()
+        })
+        .load_all()
+        .await
+        .unwrap();
+
+        assert_eq!(file_list.components.len(), 4);
+        assert!(file_list
+            .components
+            .contains_key("remote.near/widget/WithStyle"));
+        assert!(file_list
+            .components
+            .contains_key("test.near/widget/NoStyle"));
+
+        remote.shutdown();
+        join_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tar_source() {
+        let file_list = RoutesOptions::new(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/webengine".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            tar_source: Some("./test/components.tar.gz".into()),
+            ..Default::default()
+        })
+        .load_all()
+        .await
+        .unwrap();
+
+        assert_eq!(file_list.components.len(), 5);
+        assert!(file_list.components.contains_key("test.near/widget/Sub.a"));
+        assert!(file_list
+            .components
+            .contains_key("test.near/widget/NoStyle"));
+    }
+
+    #[tokio::test]
+    async fn test_key_pattern_strict() {
+        let config = ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/webengine".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            key_pattern: Some(key_pattern("test\\.near/widget/With.*").unwrap()),
+            ..Default::default()
+        };
+
+        let file_list = RoutesOptions::new(config.clone()).load_all().await.unwrap();
+        assert_eq!(file_list.components.len(), 2);
+
+        let err = RoutesOptions::new(ResolvedConfig {
+            strict: true,
+            ..config
+        })
+        .load_all()
+        .await
+        .unwrap_err();
+        assert!(err.to_string().ends_with(": test.near/widget/NoStyle"));
+    }
+
+    #[tokio::test]
+    async fn test_check_components() {
+        let config = ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/webengine".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            key_pattern: Some(key_pattern("test\\.near/widget/With.*").unwrap()),
+            ..Default::default()
+        };
+
+        let file_list = check_components(config.clone()).await.unwrap();
+        assert_eq!(file_list.components.len(), 2);
+
+        assert!(check_components(ResolvedConfig {
+            strict: true,
+            ..config
+        })
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_size_budget_strict() {
+        let config = ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/max-file-size".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            size_budget: Some(SizeBudget {
+                components: HashMap::from([("test.near/widget/Large".to_string(), 0.05)]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let file_list = RoutesOptions::new(config.clone()).load_all().await.unwrap();
+        assert_eq!(file_list.components.len(), 2);
+
+        let err = RoutesOptions::new(ResolvedConfig {
+            strict: true,
+            ..config
+        })
+        .load_all()
+        .await
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Components are over the size budget: test.near/widget/Large is 0.1 KB, over its budget of 0.05 KB"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_first_request_output_file() {
+        let output_file = std::env::temp_dir().join("bos-loader-test-first-request.json");
+        let options = RoutesOptions::new(ResolvedConfig {
+            accounts: vec![AccountPath {
+                path: "./test/webengine".into(),
+                account: "test.near".to_string(),
+                prefix: None,
+            }],
+            exit_after_first_request: true,
+            output_file: Some(output_file.clone()),
+            ..Default::default()
+        });
+
+        let response = warp::test::request()
+            .path("/")
+            .reply(&routes(options.clone()))
+            .await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(*options.first_request.borrow(), Some(true));
+        assert_eq!(
+            std::fs::read(&output_file).unwrap(),
+            response.body().to_vec()
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use crate::{components::ComponentCode, key::ComponentKey};
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::{RwLock, RwLockReadGuard};
+
+/// The components of the latest load, versioned so readers can tell when they changed
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot {
+    inner: Arc<RwLock<(u64, HashMap<ComponentKey, ComponentCode>)>>,
+}
+
+/// Read access to a [`Snapshot`], holding off writers while alive
+pub struct SnapshotGuard<'a> {
+    guard: RwLockReadGuard<'a, (u64, HashMap<ComponentKey, ComponentCode>)>,
+}
+
+impl SnapshotGuard<'_> {
+    /// Incremented on every write, `0` until the first one
+    pub fn version(&self) -> u64 {
+        self.guard.0
+    }
+
+    pub fn components(&self) -> &HashMap<ComponentKey, ComponentCode> {
+        &self.guard.1
+    }
+}
+
+impl Snapshot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn read(&self) -> SnapshotGuard<'_> {
+        SnapshotGuard {
+            guard: self.inner.read().await,
+        }
+    }
+
+    /// Replaces the components and increments the version, returning the previous version and
+    /// components
+    pub async fn write(
+        &self,
+        components: HashMap<ComponentKey, ComponentCode>,
+    ) -> (u64, HashMap<ComponentKey, ComponentCode>) {
+        let mut inner = self.inner.write().await;
+        let version = inner.0;
+
+        std::mem::replace(&mut *inner, (version + 1, components))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_snapshot_write() {
+        let snapshot = Snapshot::new();
+        assert_eq!(snapshot.read().await.version(), 0);
+
+        let components = HashMap::from([(
+            ComponentKey::parse("test.near/widget/A").unwrap(),
+            ComponentCode {
+                code: "return <>A</>;".to_string(),
+                css: None,
+                hash: None,
+                url: None,
+            },
+        )]);
+
+        let (previous_version, previous) = snapshot.write(components.clone()).await;
+        assert_eq!(previous_version, 0);
+        assert!(previous.is_empty());
+
+        let (previous_version, previous) = snapshot.clone().write(HashMap::new()).await;
+        assert_eq!(previous_version, 1);
+        assert_eq!(previous, components);
+
+        let guard = snapshot.read().await;
+        assert_eq!(guard.version(), 2);
+        assert!(guard.components().is_empty());
+    }
+}
@@ -0,0 +1,665 @@
+use crate::{
+    access_log::format_iso8601,
+    components::{ComponentCode, COMPONENT_EXTENSIONS},
+    key::ComponentKey,
+};
+use anyhow::anyhow;
+use clap::ValueEnum;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    fs::OpenOptions,
+    io::AsyncWriteExt,
+    sync::{broadcast, mpsc},
+    time::Instant,
+};
+
+/// Number of events a subscriber can lag behind before missing some
+pub const WATCH_EVENT_CAPACITY: usize = 64;
+
+/// How long to wait for further file changes before reloading, one save often produces several
+pub(crate) const WATCH_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// How often the `--watch-trigger-file` and `--replacements-watch` files are checked for a new
+/// modification time
+pub(crate) const TRIGGER_FILE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A change to the served components
+#[derive(Clone, Debug, PartialEq)]
+pub enum WatchEvent {
+    Modified(ComponentKey),
+    Added(ComponentKey),
+    Removed(ComponentKey),
+    Error(String),
+}
+
+impl WatchEvent {
+    /// The changed component, `None` for errors
+    pub fn key(&self) -> Option<&ComponentKey> {
+        match self {
+            WatchEvent::Modified(key) | WatchEvent::Added(key) | WatchEvent::Removed(key) => {
+                Some(key)
+            }
+            WatchEvent::Error(_) => None,
+        }
+    }
+
+    /// `modify`, `add` or `remove`, `None` for errors
+    pub fn event_type(&self) -> Option<&'static str> {
+        match self {
+            WatchEvent::Modified(_) => Some("modify"),
+            WatchEvent::Added(_) => Some("add"),
+            WatchEvent::Removed(_) => Some("remove"),
+            WatchEvent::Error(_) => None,
+        }
+    }
+}
+
+/// Level watch mode logs added, modified and removed components at
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum WatchEventLogLevel {
+    /// Hidden by the request log, which shows info and above
+    Debug,
+    #[default]
+    Info,
+    Warn,
+}
+
+/// Logs a change to the served components at `level`, errors are reported by the failed load
+pub(crate) fn log_watch_event(event: &WatchEvent, level: WatchEventLogLevel) {
+    let (key, change) = match event {
+        WatchEvent::Modified(key) => (key, "modified"),
+        WatchEvent::Added(key) => (key, "added"),
+        WatchEvent::Removed(key) => (key, "removed"),
+        WatchEvent::Error(_) => return,
+    };
+
+    match level {
+        WatchEventLogLevel::Debug => tracing::debug!("Component {} {}", key, change),
+        WatchEventLogLevel::Info => tracing::info!("Component {} {}", key, change),
+        WatchEventLogLevel::Warn => tracing::warn!("Component {} {}", key, change),
+    }
+}
+
+/// A line of the `--watch-emit-events` log
+#[derive(Serialize, Debug, PartialEq)]
+struct WatchEventRecord<'a> {
+    timestamp: &'a str,
+    event_type: &'static str,
+    key: &'a ComponentKey,
+    /// The file the component was loaded from, `null` for components of archives, remote
+    /// sources and test mode
+    path: Option<&'a Path>,
+}
+
+/// Appends one JSON line per change in `events` to `path`, creating it when missing. Errors
+/// aren't written, paths are looked up in `source_paths`
+pub(crate) async fn append_watch_events(
+    path: &Path,
+    events: &[WatchEvent],
+    source_paths: &HashMap<ComponentKey, PathBuf>,
+    time: SystemTime,
+) -> Result<(), anyhow::Error> {
+    let timestamp = format_iso8601(time);
+    let mut lines = String::new();
+
+    for event in events {
+        let (Some(event_type), Some(key)) = (event.event_type(), event.key()) else {
+            continue;
+        };
+
+        lines.push_str(&serde_json::to_string(&WatchEventRecord {
+            timestamp: &timestamp,
+            event_type,
+            key,
+            path: source_paths.get(key).map(PathBuf::as_path),
+        })?);
+        lines.push('\n');
+    }
+
+    if lines.is_empty() {
+        return Ok(());
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .map_err(|err| anyhow!("Failed to open file {:?} \n Error: {:?}", path, err))?;
+
+    file.write_all(lines.as_bytes())
+        .await
+        .map_err(|err| anyhow!("Failed to write file {:?} \n Error: {:?}", path, err))?;
+
+    // tokio writes in the background, the lines are only in the file once flushed
+    file.flush()
+        .await
+        .map_err(|err| anyhow!("Failed to write file {:?} \n Error: {:?}", path, err))
+}
+
+/// Creates the channel watch events are broadcast on, subscribe with [`broadcast::Sender::subscribe`]
+pub fn watch_channel() -> (
+    broadcast::Sender<WatchEvent>,
+    broadcast::Receiver<WatchEvent>,
+) {
+    broadcast::channel(WATCH_EVENT_CAPACITY)
+}
+
+/// Lists the changes between two loads, sorted by key
+pub(crate) fn diff_components(
+    previous: &HashMap<ComponentKey, ComponentCode>,
+    current: &HashMap<ComponentKey, ComponentCode>,
+) -> Vec<WatchEvent> {
+    let mut changes = current
+        .iter()
+        .filter_map(|(key, code)| match previous.get(key) {
+            None => Some((key, WatchEvent::Added(key.clone()))),
+            Some(previous_code) if previous_code != code => {
+                Some((key, WatchEvent::Modified(key.clone())))
+            }
+            Some(_) => None,
+        })
+        .chain(
+            previous
+                .keys()
+                .filter(|key| !current.contains_key(*key))
+                .map(|key| (key, WatchEvent::Removed(key.clone()))),
+        )
+        .collect::<Vec<(&ComponentKey, WatchEvent)>>();
+    changes.sort_by_key(|(key, _)| *key);
+
+    changes.into_iter().map(|(_, event)| event).collect()
+}
+
+/// Removed components held back by `--watch-coalesce-timeout`, so a rename or atomic save seen by
+/// two reloads gives a single modification instead of a removal and an addition
+#[derive(Debug, Default)]
+pub(crate) struct PendingRemovals(Vec<(ComponentKey, Option<String>, Instant)>);
+
+impl PendingRemovals {
+    /// Holds the removals of `events` until `now + timeout`. An addition of a held key, or of a
+    /// key with the same `stem`, takes its removal back and becomes a modification of the added
+    /// key. Returns the events to send now
+    pub(crate) fn coalesce(
+        &mut self,
+        events: Vec<WatchEvent>,
+        now: Instant,
+        timeout: Duration,
+        stem: impl Fn(&ComponentKey) -> Option<String>,
+    ) -> Vec<WatchEvent> {
+        let (removals, events): (Vec<WatchEvent>, Vec<WatchEvent>) = events
+            .into_iter()
+            .partition(|event| matches!(event, WatchEvent::Removed(_)));
+
+        for removal in removals {
+            if let WatchEvent::Removed(key) = removal {
+                let key_stem = stem(&key);
+                self.0.push((key, key_stem, now + timeout));
+            }
+        }
+
+        events
+            .into_iter()
+            .map(|event| match event {
+                WatchEvent::Added(key) => {
+                    let key_stem = stem(&key);
+                    let held = self.0.iter().position(|(removed, removed_stem, _)| {
+                        *removed == key || (key_stem.is_some() && *removed_stem == key_stem)
+                    });
+
+                    match held {
+                        Some(index) => {
+                            self.0.remove(index);
+                            WatchEvent::Modified(key)
+                        }
+                        None => WatchEvent::Added(key),
+                    }
+                }
+                event => event,
+            })
+            .collect()
+    }
+
+    /// Takes the removals whose window has ended by `now`
+    pub(crate) fn expired(&mut self, now: Instant) -> Vec<WatchEvent> {
+        let (expired, held) = std::mem::take(&mut self.0)
+            .into_iter()
+            .partition(|(_, _, deadline)| *deadline <= now);
+        self.0 = held;
+
+        expired
+            .into_iter()
+            .map(|(key, _, _)| WatchEvent::Removed(key))
+            .collect()
+    }
+
+    pub(crate) fn contains(&self, key: &ComponentKey) -> bool {
+        self.0.iter().any(|(removed, _, _)| removed == key)
+    }
+}
+
+fn is_component_file(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        COMPONENT_EXTENSIONS
+            .iter()
+            .any(|component_extension| extension == *component_extension)
+    })
+}
+
+/// Watches `paths` recursively, notifying the returned receiver whenever a component file under
+/// them is created, modified or removed. Notifications stop once the watcher is dropped
+pub(crate) fn watch_component_files(
+    paths: &[PathBuf],
+) -> Result<(RecommendedWatcher, mpsc::UnboundedReceiver<()>), anyhow::Error> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        match event {
+            Ok(event) => {
+                if matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                ) && event.paths.iter().any(|path| is_component_file(path))
+                {
+                    // an error only means the watcher is being dropped
+                    let _ = sender.send(());
+                }
+            }
+            Err(err) => eprintln!("Warning: file watcher error: {}", err),
+        }
+    })
+    .map_err(|err| anyhow!("Failed to start file watcher \n Error: {:?}", err))?;
+
+    for path in paths {
+        watcher
+            .watch(path, RecursiveMode::Recursive)
+            .map_err(|err| anyhow!("Failed to watch path {:?} \n Error: {:?}", path, err))?;
+    }
+
+    Ok((watcher, receiver))
+}
+
+/// Waits for the next batch of changes from [`watch_component_files`]: the first change, those
+/// following within [`WATCH_DEBOUNCE`] and any more until `cooldown_end`, which all call for a
+/// single reload. Returns `false` once the watcher has stopped
+pub(crate) async fn next_changes(
+    changes: &mut mpsc::UnboundedReceiver<()>,
+    cooldown_end: Option<Instant>,
+) -> bool {
+    if changes.recv().await.is_none() {
+        return false;
+    }
+
+    tokio::time::sleep(WATCH_DEBOUNCE).await;
+    if let Some(cooldown_end) = cooldown_end {
+        tokio::time::sleep_until(cooldown_end).await;
+    }
+    while changes.try_recv().is_ok() {}
+
+    true
+}
+
+/// Polls the modification time of `path`, notifying the returned receiver whenever it changes,
+/// e.g. on `touch`. Unlike [`watch_component_files`] this doesn't rely on filesystem events, and
+/// the file doesn't need to exist yet. Polling stops once the receiver is dropped
+pub(crate) fn watch_trigger_file(path: PathBuf) -> mpsc::UnboundedReceiver<()> {
+    watch_files(vec![path])
+}
+
+/// Polls the modification times of `paths` like [`watch_trigger_file`], notifying the returned
+/// receiver once per poll in which any of them changed
+pub(crate) fn watch_files(paths: Vec<PathBuf>) -> mpsc::UnboundedReceiver<()> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    // read before returning, so a change made right after is caught
+    let mut last_modified = paths
+        .iter()
+        .map(|path| {
+            std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+        .collect::<Vec<_>>();
+
+    tokio::spawn(async move {
+        while !sender.is_closed() {
+            tokio::time::sleep(TRIGGER_FILE_POLL_INTERVAL).await;
+
+            let mut changed = false;
+            for (path, last_modified) in paths.iter().zip(last_modified.iter_mut()) {
+                let current = modified_time(path).await;
+                // removing the file isn't a change
+                changed |= current.is_some() && current != *last_modified;
+                *last_modified = current;
+            }
+
+            if changed {
+                let _ = sender.send(());
+            }
+        }
+    });
+
+    receiver
+}
+
+async fn modified_time(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Runs the `--reload-script` with the outcome of a reload in `BOS_COMPONENT_COUNT` and
+/// `BOS_RELOAD_MS`, warning when it can't be started or exits with an error
+pub(crate) async fn run_reload_script(script: &Path, component_count: usize, duration: Duration) {
+    let status = tokio::process::Command::new(script)
+        .env("BOS_COMPONENT_COUNT", component_count.to_string())
+        .env("BOS_RELOAD_MS", duration.as_millis().to_string())
+        .status()
+        .await;
+
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!("Warning: reload script {:?} exited with {}", script, status),
+        Err(err) => eprintln!(
+            "Warning: could not run reload script {:?} \n Error: {:?}",
+            script, err
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn component(code: &str) -> ComponentCode {
+        ComponentCode {
+            code: code.to_string(),
+            css: None,
+            hash: None,
+            url: None,
+        }
+    }
+
+    fn key(key: &str) -> ComponentKey {
+        ComponentKey::parse(key).unwrap()
+    }
+
+    #[test]
+    fn test_diff_components() {
+        let previous = HashMap::from([
+            (key("test.near/widget/Same"), component("same")),
+            (key("test.near/widget/Changed"), component("before")),
+            (key("test.near/widget/Removed"), component("removed")),
+        ]);
+        let current = HashMap::from([
+            (key("test.near/widget/Same"), component("same")),
+            (key("test.near/widget/Changed"), component("after")),
+            (key("test.near/widget/Added"), component("added")),
+        ]);
+
+        assert_eq!(
+            diff_components(&previous, &current),
+            vec![
+                WatchEvent::Added(key("test.near/widget/Added")),
+                WatchEvent::Modified(key("test.near/widget/Changed")),
+                WatchEvent::Removed(key("test.near/widget/Removed")),
+            ]
+        );
+        assert!(diff_components(&current, &current).is_empty());
+    }
+
+    #[derive(Clone, Default)]
+    struct CapturedLog(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLog {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_pending_removals() {
+        let timeout = Duration::from_millis(500);
+        let start = Instant::now();
+        let stem = |key: &ComponentKey| key.rsplit('.').next().map(str::to_string);
+        let mut pending = PendingRemovals::default();
+
+        // a rename split across two batches
+        let events = pending.coalesce(
+            vec![
+                WatchEvent::Modified(key("test.near/widget/Other")),
+                WatchEvent::Removed(key("test.near/widget/old.Foo")),
+            ],
+            start,
+            timeout,
+            stem,
+        );
+        assert_eq!(
+            events,
+            [WatchEvent::Modified(key("test.near/widget/Other"))]
+        );
+        assert!(pending.contains(&key("test.near/widget/old.Foo")));
+        assert!(pending.expired(start).is_empty());
+
+        let events = pending.coalesce(
+            vec![WatchEvent::Added(key("test.near/widget/new.Foo"))],
+            start + Duration::from_millis(100),
+            timeout,
+            stem,
+        );
+        assert_eq!(
+            events,
+            [WatchEvent::Modified(key("test.near/widget/new.Foo"))]
+        );
+        assert!(!pending.contains(&key("test.near/widget/old.Foo")));
+
+        // an atomic save within one batch, and a removal nothing takes back
+        let events = pending.coalesce(
+            vec![
+                WatchEvent::Added(key("test.near/widget/Bar")),
+                WatchEvent::Removed(key("test.near/widget/Bar")),
+                WatchEvent::Removed(key("test.near/widget/Gone")),
+            ],
+            start,
+            timeout,
+            stem,
+        );
+        assert_eq!(events, [WatchEvent::Modified(key("test.near/widget/Bar"))]);
+        assert!(pending
+            .expired(start + Duration::from_millis(499))
+            .is_empty());
+        assert_eq!(
+            pending.expired(start + timeout),
+            [WatchEvent::Removed(key("test.near/widget/Gone"))]
+        );
+        assert!(!pending.contains(&key("test.near/widget/Gone")));
+
+        // keys without a stem only match themselves
+        pending.coalesce(
+            vec![WatchEvent::Removed(key("test.near/widget/a.Baz"))],
+            start,
+            timeout,
+            |_| None,
+        );
+        let events = pending.coalesce(
+            vec![WatchEvent::Added(key("test.near/widget/b.Baz"))],
+            start,
+            timeout,
+            |_| None,
+        );
+        assert_eq!(events, [WatchEvent::Added(key("test.near/widget/b.Baz"))]);
+    }
+
+    #[test]
+    fn test_log_watch_event() {
+        let log = CapturedLog::default();
+        let writer = log.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let event = WatchEvent::Modified(key("alice.near/widget/Foo"));
+
+            log_watch_event(&event, WatchEventLogLevel::Debug);
+            log_watch_event(&event, WatchEventLogLevel::Info);
+            log_watch_event(
+                &WatchEvent::Removed(key("alice.near/widget/Bar")),
+                WatchEventLogLevel::Warn,
+            );
+            log_watch_event(
+                &WatchEvent::Error("failed".to_string()),
+                WatchEventLogLevel::Warn,
+            );
+        });
+
+        let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+        let lines = log.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines.len(), 2, "{log}");
+        assert!(lines[0].contains(" INFO "), "{log}");
+        assert!(lines[0].ends_with("Component alice.near/widget/Foo modified"));
+        assert!(lines[1].contains(" WARN "), "{log}");
+        assert!(lines[1].ends_with("Component alice.near/widget/Bar removed"));
+    }
+
+    #[tokio::test]
+    async fn test_append_watch_events() {
+        let path = std::env::temp_dir().join("bos-loader-test-watch-events.ndjson");
+        let _ = std::fs::remove_file(&path);
+        let source_paths =
+            HashMap::from([(key("alice.near/widget/Foo"), PathBuf::from("./src/Foo.jsx"))]);
+        let time = std::time::UNIX_EPOCH + Duration::from_secs(971211336);
+
+        append_watch_events(
+            &path,
+            &[
+                WatchEvent::Modified(key("alice.near/widget/Foo")),
+                WatchEvent::Error("failed".to_string()),
+            ],
+            &source_paths,
+            time,
+        )
+        .await
+        .unwrap();
+        append_watch_events(
+            &path,
+            &[WatchEvent::Removed(key("alice.near/widget/Bar"))],
+            &source_paths,
+            time,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\"timestamp\":\"2000-10-10T20:55:36Z\",\"event_type\":\"modify\",\"key\":\"alice.near/widget/Foo\",\"path\":\"./src/Foo.jsx\"}\n\
+             {\"timestamp\":\"2000-10-10T20:55:36Z\",\"event_type\":\"remove\",\"key\":\"alice.near/widget/Bar\",\"path\":null}\n"
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_next_changes() {
+        let (sender, mut changes) = mpsc::unbounded_channel();
+
+        sender.send(()).unwrap();
+        sender.send(()).unwrap();
+        assert!(next_changes(&mut changes, None).await);
+        assert!(changes.try_recv().is_err());
+
+        // changes during the cooldown are batched into one reload after it
+        let cooldown = Duration::from_millis(300);
+        let started = Instant::now();
+        sender.send(()).unwrap();
+        let late_sender = sender.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            late_sender.send(()).unwrap();
+        });
+
+        assert!(next_changes(&mut changes, Some(started + cooldown)).await);
+        assert!(started.elapsed() >= cooldown);
+        assert!(changes.try_recv().is_err());
+
+        // a cooldown that has passed doesn't delay the reload
+        sender.send(()).unwrap();
+        let started = Instant::now();
+        assert!(next_changes(&mut changes, Some(started - cooldown)).await);
+        assert!(started.elapsed() < cooldown);
+
+        drop(sender);
+        assert!(!next_changes(&mut changes, None).await);
+    }
+
+    #[tokio::test]
+    async fn test_watch_trigger_file() {
+        let dir = std::env::temp_dir().join("bos-loader-test-trigger-file");
+        std::fs::create_dir_all(&dir).unwrap();
+        let trigger_file = dir.join("reload");
+        let _ = std::fs::remove_file(&trigger_file);
+
+        let mut triggers = watch_trigger_file(trigger_file.clone());
+        async fn next(
+            triggers: &mut mpsc::UnboundedReceiver<()>,
+        ) -> Result<Option<()>, tokio::time::error::Elapsed> {
+            tokio::time::timeout(TRIGGER_FILE_POLL_INTERVAL * 8, triggers.recv()).await
+        }
+
+        // creating the file counts as touching it
+        tokio::time::sleep(TRIGGER_FILE_POLL_INTERVAL * 2).await;
+        std::fs::write(&trigger_file, "").unwrap();
+        next(&mut triggers).await.unwrap().unwrap();
+
+        let touched = std::time::SystemTime::now() + Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(&trigger_file)
+            .unwrap()
+            .set_modified(touched)
+            .unwrap();
+        next(&mut triggers).await.unwrap().unwrap();
+
+        // nothing changed since
+        assert!(next(&mut triggers).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_reload_script() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join("bos-loader-test-reload-script");
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("reload.sh");
+        let output = dir.join("output");
+        let _ = std::fs::remove_file(&output);
+
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\necho \"$BOS_COMPONENT_COUNT $BOS_RELOAD_MS\" > {:?}\n",
+                output
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        run_reload_script(&script, 3, Duration::from_millis(42)).await;
+
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "3 42\n");
+    }
+}
@@ -0,0 +1,3 @@
+const items = ["one", "two", "three", "four", "five"];
+
+return <ul>{items.map((item) => <li>{item}</li>)}</ul>;
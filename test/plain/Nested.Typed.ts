@@ -0,0 +1,3 @@
+const title: string = "Typed";
+
+return <>{title}</>;